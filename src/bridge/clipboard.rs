@@ -0,0 +1,346 @@
+use std::{collections::HashMap, convert::TryInto, error::Error};
+
+use copypasta::{ClipboardContext, ClipboardProvider};
+use log::{trace, warn};
+use nvim_rs::{Neovim, Value};
+use parking_lot::Mutex;
+
+use super::tx_wrapper::TxWrapper;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync + 'static>>;
+
+lazy_static! {
+    pub static ref CLIPBOARD: Mutex<Clipboard> = Mutex::new(Clipboard::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClipboardRegister {
+    Plus,
+    Star,
+}
+
+impl ClipboardRegister {
+    pub fn parse(name: &str) -> Option<ClipboardRegister> {
+        match name {
+            "+" => Some(ClipboardRegister::Plus),
+            "*" => Some(ClipboardRegister::Star),
+            _ => None,
+        }
+    }
+}
+
+pub trait ClipboardBackend: Send {
+    fn get_contents(&mut self, register: ClipboardRegister) -> Result<String>;
+    fn set_contents(&mut self, register: ClipboardRegister, contents: String) -> Result<()>;
+}
+
+pub struct SystemClipboard {
+    clipboard: ClipboardContext,
+    primary: Option<Box<dyn ClipboardProvider>>,
+}
+
+impl SystemClipboard {
+    pub fn new() -> Result<Self> {
+        Ok(SystemClipboard {
+            clipboard: ClipboardContext::new()?,
+            primary: primary_selection(),
+        })
+    }
+
+    fn provider(&mut self, register: ClipboardRegister) -> &mut dyn ClipboardProvider {
+        match (register, self.primary.as_mut()) {
+            (ClipboardRegister::Star, Some(primary)) => primary.as_mut(),
+            _ => &mut self.clipboard,
+        }
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+))]
+fn primary_selection() -> Option<Box<dyn ClipboardProvider>> {
+    use copypasta::x11_clipboard::{Primary, X11ClipboardContext};
+    X11ClipboardContext::<Primary>::new()
+        .ok()
+        .map(|context| Box::new(context) as Box<dyn ClipboardProvider>)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+)))]
+fn primary_selection() -> Option<Box<dyn ClipboardProvider>> {
+    None
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn get_contents(&mut self, register: ClipboardRegister) -> Result<String> {
+        self.provider(register).get_contents()
+    }
+
+    fn set_contents(&mut self, register: ClipboardRegister, contents: String) -> Result<()> {
+        self.provider(register).set_contents(contents)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryClipboard {
+    contents: HashMap<ClipboardRegister, String>,
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn get_contents(&mut self, register: ClipboardRegister) -> Result<String> {
+        Ok(self.contents.get(&register).cloned().unwrap_or_default())
+    }
+
+    fn set_contents(&mut self, register: ClipboardRegister, contents: String) -> Result<()> {
+        self.contents.insert(register, contents);
+        Ok(())
+    }
+}
+
+struct CopiedContents {
+    text: String,
+    regtype: String,
+}
+
+pub struct Clipboard {
+    backend: Box<dyn ClipboardBackend>,
+    copied: HashMap<ClipboardRegister, CopiedContents>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        match SystemClipboard::new() {
+            Ok(system_clipboard) => Clipboard::with_backend(Box::new(system_clipboard)),
+            Err(error) => {
+                warn!(
+                    "System clipboard unavailable, falling back to in-memory clipboard: {}",
+                    error
+                );
+                Clipboard::with_backend(Box::new(MemoryClipboard::default()))
+            }
+        }
+    }
+
+    pub fn with_backend(backend: Box<dyn ClipboardBackend>) -> Self {
+        Clipboard {
+            backend,
+            copied: HashMap::new(),
+        }
+    }
+
+    pub fn copy(
+        &mut self,
+        register: ClipboardRegister,
+        lines: Vec<String>,
+        regtype: String,
+    ) -> Result<()> {
+        let mut text = lines.join("\n");
+        if regtype == "V" {
+            text.push('\n');
+        }
+        self.backend.set_contents(register, text.clone())?;
+        self.copied
+            .insert(register, CopiedContents { text, regtype });
+        Ok(())
+    }
+
+    pub fn paste(&mut self, register: ClipboardRegister) -> Result<(Vec<String>, String)> {
+        let text = self.get_text(register)?;
+        let regtype = match self.copied.get(&register) {
+            Some(copied) if copied.text == text => copied.regtype.clone(),
+            _ if text.ends_with('\n') => "V".to_owned(),
+            _ => "v".to_owned(),
+        };
+        let mut lines = text.split('\n').map(String::from).collect::<Vec<_>>();
+        if regtype == "V" && text.ends_with('\n') {
+            lines.pop();
+        }
+        Ok((lines, regtype))
+    }
+
+    pub fn get_text(&mut self, register: ClipboardRegister) -> Result<String> {
+        Ok(self.backend.get_contents(register)?.replace("\r\n", "\n"))
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Clipboard::new()
+    }
+}
+
+fn parse_register(value: Value) -> std::result::Result<ClipboardRegister, Value> {
+    let name: String = value
+        .try_into()
+        .map_err(|_| Value::from("Clipboard register must be a string"))?;
+    ClipboardRegister::parse(&name)
+        .ok_or_else(|| Value::from(format!("Unsupported clipboard register {}", name)))
+}
+
+pub fn handle_clipboard_get(arguments: Vec<Value>) -> std::result::Result<Value, Value> {
+    let register = parse_register(arguments.into_iter().next().unwrap_or(Value::Nil))?;
+    let (lines, regtype) = CLIPBOARD
+        .lock()
+        .paste(register)
+        .map_err(|error| Value::from(error.to_string()))?;
+    trace!(
+        "Clipboard paste from {:?} with regtype {}",
+        register,
+        regtype
+    );
+    Ok(Value::Array(vec![
+        Value::Array(lines.into_iter().map(Value::from).collect()),
+        Value::from(regtype),
+    ]))
+}
+
+pub fn handle_clipboard_set(arguments: Vec<Value>) -> std::result::Result<Value, Value> {
+    let mut arguments = arguments.into_iter();
+    let register = parse_register(arguments.next().unwrap_or(Value::Nil))?;
+    let lines: Vec<Value> = arguments
+        .next()
+        .unwrap_or(Value::Nil)
+        .try_into()
+        .map_err(|_| Value::from("Clipboard lines must be a list"))?;
+    let lines = lines
+        .into_iter()
+        .map(|line| line.try_into())
+        .collect::<std::result::Result<Vec<String>, _>>()
+        .map_err(|_| Value::from("Clipboard lines must be strings"))?;
+    let regtype: String = arguments
+        .next()
+        .and_then(|regtype| regtype.try_into().ok())
+        .unwrap_or_else(|| "v".to_owned());
+    trace!("Clipboard copy to {:?} with regtype {}", register, regtype);
+    CLIPBOARD
+        .lock()
+        .copy(register, lines, regtype)
+        .map_err(|error| Value::from(error.to_string()))?;
+    Ok(Value::Nil)
+}
+
+pub async fn register_clipboard_provider(nvim: &Neovim<TxWrapper>, channel: u64) {
+    let vimscript = format!(
+        concat!(
+            "if !exists('g:clipboard')\n",
+            "  let g:clipboard = {{\n",
+            "    \\ 'name': 'xvim',\n",
+            "    \\ 'copy': {{\n",
+            "    \\   '+': {{lines, regtype -> rpcrequest({0}, 'xvim.clipboard_set', '+', lines, regtype)}},\n",
+            "    \\   '*': {{lines, regtype -> rpcrequest({0}, 'xvim.clipboard_set', '*', lines, regtype)}},\n",
+            "    \\ }},\n",
+            "    \\ 'paste': {{\n",
+            "    \\   '+': {{-> rpcrequest({0}, 'xvim.clipboard_get', '+')}},\n",
+            "    \\   '*': {{-> rpcrequest({0}, 'xvim.clipboard_get', '*')}},\n",
+            "    \\ }},\n",
+            "    \\ 'cache_enabled': 0,\n",
+            "    \\ }}\n",
+            "  unlet! g:loaded_clipboard_provider\n",
+            "  runtime autoload/provider/clipboard.vim\n",
+            "endif",
+        ),
+        channel
+    );
+    if let Err(error) = nvim.exec(&vimscript, false).await {
+        warn!("Could not register clipboard provider: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts line endings the way clipboards on Windows do
+    #[derive(Default)]
+    struct CrlfClipboard(MemoryClipboard);
+
+    impl ClipboardBackend for CrlfClipboard {
+        fn get_contents(&mut self, register: ClipboardRegister) -> Result<String> {
+            self.0.get_contents(register)
+        }
+
+        fn set_contents(&mut self, register: ClipboardRegister, contents: String) -> Result<()> {
+            let contents = contents.replace('\n', "\r\n");
+            self.0.set_contents(register, contents)
+        }
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn round_trip(backend: Box<dyn ClipboardBackend>, copied: &[&str], regtype: &str) {
+        let mut clipboard = Clipboard::with_backend(backend);
+        for register in [ClipboardRegister::Plus, ClipboardRegister::Star].iter() {
+            clipboard
+                .copy(*register, lines(copied), regtype.to_owned())
+                .unwrap();
+            assert_eq!(
+                clipboard.paste(*register).unwrap(),
+                (lines(copied), regtype.to_owned()),
+                "{:?} with regtype {:?}",
+                register,
+                regtype
+            );
+        }
+    }
+
+    #[test]
+    fn characterwise_contents_round_trip() {
+        round_trip(Box::new(MemoryClipboard::default()), &["foo", "bar"], "v");
+        round_trip(Box::new(MemoryClipboard::default()), &["foo", ""], "v");
+    }
+
+    #[test]
+    fn linewise_contents_round_trip() {
+        round_trip(Box::new(MemoryClipboard::default()), &["foo", "bar"], "V");
+    }
+
+    #[test]
+    fn blockwise_contents_round_trip() {
+        let backend = Box::new(MemoryClipboard::default());
+        round_trip(backend, &["ab", "cd"], "\u{16}2");
+    }
+
+    #[test]
+    fn regtypes_survive_crlf_conversion() {
+        round_trip(Box::new(CrlfClipboard::default()), &["foo", "bar"], "v");
+        round_trip(Box::new(CrlfClipboard::default()), &["foo", "bar"], "V");
+        round_trip(Box::new(CrlfClipboard::default()), &["ab", "cd"], "\u{16}2");
+    }
+
+    #[test]
+    fn contents_from_other_applications_are_normalised() {
+        let mut backend = MemoryClipboard::default();
+        backend
+            .set_contents(ClipboardRegister::Plus, "one\r\ntwo\r\n".to_owned())
+            .unwrap();
+        backend
+            .set_contents(ClipboardRegister::Star, "one\r\ntwo".to_owned())
+            .unwrap();
+        let mut clipboard = Clipboard::with_backend(Box::new(backend));
+        assert_eq!(
+            clipboard.paste(ClipboardRegister::Plus).unwrap(),
+            (lines(&["one", "two"]), "V".to_owned())
+        );
+        assert_eq!(
+            clipboard.paste(ClipboardRegister::Star).unwrap(),
+            (lines(&["one", "two"]), "v".to_owned())
+        );
+    }
+
+    #[test]
+    fn registers_are_independent() {
+        let mut clipboard = Clipboard::with_backend(Box::new(MemoryClipboard::default()));
+        clipboard
+            .copy(ClipboardRegister::Plus, lines(&["plus"]), "V".to_owned())
+            .unwrap();
+        assert_eq!(
+            clipboard.paste(ClipboardRegister::Star).unwrap(),
+            (lines(&[""]), "v".to_owned())
+        );
+    }
+}
@@ -1,9 +1,11 @@
-use log::{debug, trace};
+use log::{debug, error, trace};
 use nvim_rs::Value;
+use serde::{Serialize, Serializer};
+use skia_safe::Color4f;
 use std::convert::TryInto;
 use std::fmt;
 
-use crate::editor::{Colors, CursorMode, CursorShape, Style};
+use crate::editor::{Colors, CursorMode, CursorShape, Style, UnderlineStyle};
 
 #[derive(Clone, Debug)]
 pub enum ParseError {
@@ -40,7 +42,7 @@ impl fmt::Display for ParseError {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct GridLineCell {
     pub text: String,
     pub highlight_id: Option<u64>,
@@ -49,7 +51,7 @@ pub struct GridLineCell {
 
 pub type StyledContent = Vec<(u64, String)>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum MessageKind {
     Unknown,
     Confirm,
@@ -86,7 +88,14 @@ impl MessageKind {
     }
 }
 
-#[derive(Clone, Debug)]
+fn serialize_unknown_value<S: Serializer>(
+    value: &Value,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub enum GuiOption {
     ArabicShape(bool),
     AmbiWidth(String),
@@ -98,10 +107,31 @@ pub enum GuiOption {
     Pumblend(u64),
     ShowTabLine(u64),
     TermGuiColors(bool),
-    Unknown(String, Value),
+    Unknown(
+        String,
+        #[serde(serialize_with = "serialize_unknown_value")] Value,
+    ),
 }
 
-#[derive(Clone, Debug)]
+impl GuiOption {
+    pub fn name(&self) -> &str {
+        match self {
+            GuiOption::ArabicShape(_) => "arabicshape",
+            GuiOption::AmbiWidth(_) => "ambiwidth",
+            GuiOption::Emoji(_) => "emoji",
+            GuiOption::GuiFont(_) => "guifont",
+            GuiOption::GuiFontSet(_) => "guifontset",
+            GuiOption::GuiFontWide(_) => "guifontwide",
+            GuiOption::LineSpace(_) => "linespace",
+            GuiOption::Pumblend(_) => "pumblend",
+            GuiOption::ShowTabLine(_) => "showtabline",
+            GuiOption::TermGuiColors(_) => "termguicolors",
+            GuiOption::Unknown(name, _) => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub enum WindowAnchor {
     NorthWest,
     NorthEast,
@@ -177,7 +207,7 @@ pub struct ChannelInfo {
     pub client: Option<ClientInfo>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum EditorMode {
     Normal,
     Insert,
@@ -187,7 +217,7 @@ pub enum EditorMode {
     Unknown(String),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum RedrawEvent {
     SetTitle {
         title: String,
@@ -219,6 +249,10 @@ pub enum RedrawEvent {
         id: u64,
         style: Style,
     },
+    HighlightGroupSet {
+        name: String,
+        id: u64,
+    },
     GridLine {
         grid: u64,
         row: u64,
@@ -263,6 +297,7 @@ pub enum RedrawEvent {
     },
     WindowExternalPosition {
         grid: u64,
+        window: u64,
     },
     WindowHide {
         grid: u64,
@@ -282,6 +317,7 @@ pub enum RedrawEvent {
         bottom_line: f64,
         current_line: f64,
         current_column: f64,
+        scroll_delta: Option<f64>,
     },
     CommandLineShow {
         content: StyledContent,
@@ -337,8 +373,12 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
     let mut parsed_events = Vec::with_capacity(event_contents.len());
     macro_rules! for_parse {
         ($func: expr) => {
+            // A malformed event is dropped on its own, the rest of the batch still applies
             for event in event_contents {
-                parsed_events.push($func(parse_array(event)?)?);
+                match parse_array(event).and_then($func) {
+                    Ok(parsed_event) => parsed_events.push(parsed_event),
+                    Err(error) => error!("Skipped {} event: {}", event_name, error),
+                }
             }
         };
     }
@@ -349,6 +389,75 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
         "mode_info_set" => {
             for_parse!(parse_mode_info_set);
         }
+        "option_set" => {
+            for_parse!(parse_option_set);
+        }
+        "mode_change" => {
+            for_parse!(parse_mode_change);
+        }
+        "flush" => {
+            for _ in event_contents {
+                parsed_events.push(RedrawEvent::Flush);
+            }
+        }
+        "busy_start" => {
+            for _ in event_contents {
+                parsed_events.push(RedrawEvent::BusyStart);
+            }
+        }
+        "busy_stop" => {
+            for _ in event_contents {
+                parsed_events.push(RedrawEvent::BusyStop);
+            }
+        }
+        "grid_cursor_goto" => {
+            for_parse!(parse_cursor_goto);
+        }
+        "default_colors_set" => {
+            for_parse!(parse_default_colors);
+        }
+        "hl_attr_define" => {
+            for_parse!(parse_hl_attr_define);
+        }
+        "hl_group_set" => {
+            for_parse!(parse_hl_group_set);
+        }
+        "grid_resize" => {
+            for_parse!(parse_grid_resize);
+        }
+        "grid_line" => {
+            for_parse!(parse_grid_line);
+        }
+        "grid_clear" => {
+            for_parse!(parse_clear);
+        }
+        "grid_destroy" => {
+            for_parse!(parse_destroy);
+        }
+        "grid_scroll" => {
+            for_parse!(parse_grid_scroll);
+        }
+        "win_pos" => {
+            for_parse!(parse_win_pos);
+        }
+        "win_float_pos" => {
+            for_parse!(parse_win_float_pos);
+        }
+        "win_external_pos" => {
+            for_parse!(parse_win_external_pos);
+        }
+        "win_hide" => {
+            for_parse!(parse_win_hide);
+        }
+        "win_close" => {
+            for_parse!(parse_win_close);
+        }
+        "msg_set_pos" => {
+            for_parse!(parse_msg_set_pos);
+        }
+        "win_viewport" => {
+            for_parse!(parse_win_viewport);
+        }
         _ => {
             trace!("un-parsed event {}", event_name);
         }
@@ -392,6 +501,363 @@ fn parse_mode_info_set(mode_info_set_arguments: Vec<Value>) -> Result<RedrawEven
     Ok(RedrawEvent::ModeInfoSet {cursor_modes})
 }
 
+fn parse_win_pos(win_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, _window, start_row, start_column, width, height] = extract_values(
+        win_pos_arguments,
+        [Value::Nil, Value::Nil, Value::Nil, Value::Nil, Value::Nil, Value::Nil],
+    )?;
+    Ok(RedrawEvent::WindowPosition {
+        grid: parse_u64(grid)?,
+        start_row: parse_u64(start_row)?,
+        start_column: parse_u64(start_column)?,
+        width: parse_u64(width)?,
+        height: parse_u64(height)?,
+    })
+}
+
+fn parse_window_anchor(value: Value) -> Result<WindowAnchor> {
+    match value.as_str() {
+        Some("NW") => Ok(WindowAnchor::NorthWest),
+        Some("NE") => Ok(WindowAnchor::NorthEast),
+        Some("SW") => Ok(WindowAnchor::SouthWest),
+        Some("SE") => Ok(WindowAnchor::SouthEast),
+        _ => Err(ParseError::WindowAnchor(value)),
+    }
+}
+
+fn parse_win_float_pos(mut win_float_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    // The zindex argument only exists since neovim 0.5
+    win_float_pos_arguments.resize(8, Value::Nil);
+    let [grid, _window, anchor, anchor_grid, anchor_row, anchor_column, focusable, sort_order] =
+        extract_values(
+            win_float_pos_arguments,
+            [
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+            ],
+        )?;
+    Ok(RedrawEvent::WindowFloatPosition {
+        grid: parse_u64(grid)?,
+        anchor: parse_window_anchor(anchor)?,
+        anchor_grid: parse_u64(anchor_grid)?,
+        anchor_row: parse_f64(anchor_row)?,
+        anchor_column: parse_f64(anchor_column)?,
+        focusable: parse_bool(focusable)?,
+        sort_order: match sort_order {
+            Value::Nil => None,
+            sort_order => Some(parse_u64(sort_order)?),
+        },
+    })
+}
+
+/// Window handles arrive as msgpack ext values wrapping an encoded integer
+fn parse_window_handle(value: Value) -> Result<u64> {
+    let bytes = match &value {
+        Value::Ext(_, bytes) => bytes.as_slice(),
+        _ => return Err(ParseError::Format(format!("{:?}", value))),
+    };
+    let handle = match bytes {
+        [byte] if *byte < 0x80 => Some(*byte as u64),
+        [0xcc, byte] => Some(*byte as u64),
+        [0xcd, rest @ ..] if rest.len() == 2 => Some(u16::from_be_bytes([rest[0], rest[1]]) as u64),
+        [0xce, rest @ ..] if rest.len() == 4 => {
+            Some(u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as u64)
+        }
+        _ => None,
+    };
+    handle.ok_or_else(|| ParseError::Format(format!("{:?}", value)))
+}
+
+fn parse_win_external_pos(win_external_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, window] = extract_values(win_external_pos_arguments, [Value::Nil, Value::Nil])?;
+    Ok(RedrawEvent::WindowExternalPosition {
+        grid: parse_u64(grid)?,
+        window: parse_window_handle(window)?,
+    })
+}
+
+fn parse_win_hide(win_hide_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid] = extract_values(win_hide_arguments, [Value::Nil])?;
+    Ok(RedrawEvent::WindowHide {
+        grid: parse_u64(grid)?,
+    })
+}
+
+fn parse_win_close(win_close_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid] = extract_values(win_close_arguments, [Value::Nil])?;
+    Ok(RedrawEvent::WindowClose {
+        grid: parse_u64(grid)?,
+    })
+}
+
+fn parse_win_viewport(mut win_viewport_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    // line_count and scroll_delta only exist since neovim 0.9
+    win_viewport_arguments.resize(8, Value::Nil);
+    let [grid, _window, top_line, bottom_line, current_line, current_column, _line_count, scroll_delta] =
+        extract_values(
+            win_viewport_arguments,
+            [
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+                Value::Nil,
+            ],
+        )?;
+    Ok(RedrawEvent::WindowViewport {
+        grid: parse_u64(grid)?,
+        top_line: parse_f64(top_line)?,
+        bottom_line: parse_f64(bottom_line)?,
+        current_line: parse_f64(current_line)?,
+        current_column: parse_f64(current_column)?,
+        scroll_delta: match scroll_delta {
+            Value::Nil => None,
+            scroll_delta => Some(parse_f64(scroll_delta)?),
+        },
+    })
+}
+
+fn parse_msg_set_pos(msg_set_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, row, scrolled, separator_character] = extract_values(
+        msg_set_pos_arguments,
+        [Value::Nil, Value::Nil, Value::Nil, Value::Nil],
+    )?;
+    Ok(RedrawEvent::MessageSetPosition {
+        grid: parse_u64(grid)?,
+        row: parse_u64(row)?,
+        scrolled: parse_bool(scrolled)?,
+        separator_character: parse_string(separator_character)?,
+    })
+}
+
+fn parse_mode_change(mode_change_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [mode, mode_index] = extract_values(mode_change_arguments, [Value::Nil, Value::Nil])?;
+    let mode_name = parse_string(mode)?;
+    let mode = match mode_name.as_str() {
+        "normal" => EditorMode::Normal,
+        "insert" => EditorMode::Insert,
+        "visual" => EditorMode::Visual,
+        "replace" => EditorMode::Replace,
+        "cmdline_normal" => EditorMode::CmdLine,
+        _ => EditorMode::Unknown(mode_name),
+    };
+    Ok(RedrawEvent::ModeChange {
+        mode,
+        mode_index: parse_u64(mode_index)?,
+    })
+}
+
+#[inline]
+fn parse_cursor_goto(cursor_goto_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, row, column] =
+        extract_values(cursor_goto_arguments, [Value::Nil, Value::Nil, Value::Nil])?;
+    Ok(RedrawEvent::CursorGoto {
+        grid: parse_u64(grid)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+    })
+}
+
+fn parse_option_set(option_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, value] = extract_values(option_set_arguments, [Value::Nil, Value::Nil])?;
+    let name = parse_string(name)?;
+    let gui_option = match name.as_str() {
+        "arabicshape" => GuiOption::ArabicShape(parse_bool(value)?),
+        "ambiwidth" => GuiOption::AmbiWidth(parse_string(value)?),
+        "emoji" => GuiOption::Emoji(parse_bool(value)?),
+        "guifont" => GuiOption::GuiFont(parse_string(value)?),
+        "guifontset" => GuiOption::GuiFontSet(parse_string(value)?),
+        "guifontwide" => GuiOption::GuiFontWide(parse_string(value)?),
+        // Lines can not overlap, so negative values draw like 0
+        "linespace" => GuiOption::LineSpace(parse_i64(value)?.max(0) as u64),
+        "pumblend" => GuiOption::Pumblend(parse_u64(value)?),
+        "showtabline" => GuiOption::ShowTabLine(parse_u64(value)?),
+        "termguicolors" => GuiOption::TermGuiColors(parse_bool(value)?),
+        _ => GuiOption::Unknown(name, value),
+    };
+    Ok(RedrawEvent::OptionSet { gui_option })
+}
+
+fn unpack_color(packed_color: u64) -> Color4f {
+    let packed_color = packed_color as u32;
+    let r = ((packed_color & 0x00ff_0000) >> 16) as f32;
+    let g = ((packed_color & 0xff00) >> 8) as f32;
+    let b = (packed_color & 0xff) as f32;
+    Color4f {
+        r: r / 255.0,
+        g: g / 255.0,
+        b: b / 255.0,
+        a: 1.0,
+    }
+}
+
+/// Neovim sends -1 for colors which are not set
+fn parse_color(color_value: Value) -> Result<Option<Color4f>> {
+    let packed_color = parse_i64(color_value)?;
+    Ok(if packed_color < 0 {
+        None
+    } else {
+        Some(unpack_color(packed_color as u64))
+    })
+}
+
+fn parse_default_colors(default_colors_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [foreground, background, special, _term_foreground, _term_background] = extract_values(
+        default_colors_arguments,
+        [Value::Nil, Value::Nil, Value::Nil, Value::Nil, Value::Nil],
+    )?;
+    Ok(RedrawEvent::DefaultColorsSet {
+        colors: Colors::new(
+            parse_color(foreground)?,
+            parse_color(background)?,
+            parse_color(special)?,
+        ),
+    })
+}
+
+fn parse_style(style_map: Value) -> Result<Style> {
+    let attributes = parse_map(style_map)?;
+    let mut style = Style::new(Colors::new(None, None, None));
+    for attribute in attributes {
+        if let (Value::String(name), value) = attribute {
+            match (name.as_str().unwrap_or_default(), value) {
+                ("foreground", value) => style.colors.foreground = parse_color(value)?,
+                ("background", value) => style.colors.background = parse_color(value)?,
+                ("special", value) => style.colors.special = parse_color(value)?,
+                ("reverse", value) => style.reverse = parse_bool(value)?,
+                ("italic", value) => style.italic = parse_bool(value)?,
+                ("bold", value) => style.bold = parse_bool(value)?,
+                ("strikethrough", value) => style.strikethrough = parse_bool(value)?,
+                ("blend", value) => style.blend = parse_u64(value)?.min(100) as u8,
+                (attribute_name, value) => {
+                    match UnderlineStyle::from_attribute_name(attribute_name) {
+                        Some(underline_style) => {
+                            if parse_bool(value)? {
+                                style.underline = Some(underline_style);
+                            }
+                        }
+                        None => debug!("Ignored style attribute: {}", name),
+                    }
+                }
+            }
+        } else {
+            debug!("Invalid attribute format: ({}, {})", attribute.0, attribute.1);
+        }
+    }
+    Ok(style)
+}
+
+fn parse_hl_attr_define(hl_attr_define_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [id, attributes, _terminal_attributes, _info] = extract_values(
+        hl_attr_define_arguments,
+        [Value::Nil, Value::Nil, Value::Nil, Value::Nil],
+    )?;
+    Ok(RedrawEvent::HighlightAttributesDefine {
+        id: parse_u64(id)?,
+        style: parse_style(attributes)?,
+    })
+}
+
+fn parse_hl_group_set(hl_group_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, id] = extract_values(hl_group_set_arguments, [Value::Nil, Value::Nil])?;
+    Ok(RedrawEvent::HighlightGroupSet {
+        name: parse_string(name)?,
+        id: parse_u64(id)?,
+    })
+}
+
+fn parse_grid_resize(grid_resize_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, width, height] =
+        extract_values(grid_resize_arguments, [Value::Nil, Value::Nil, Value::Nil])?;
+    Ok(RedrawEvent::Resize {
+        grid: parse_u64(grid)?,
+        width: parse_u64(width)?,
+        height: parse_u64(height)?,
+    })
+}
+
+fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
+    let mut cell_contents = parse_array(grid_line_cell)?.into_iter();
+    let text = cell_contents
+        .next()
+        .ok_or_else(|| ParseError::Format("empty grid line cell".to_owned()))
+        .and_then(parse_string)?;
+    let highlight_id = cell_contents.next().map(parse_u64).transpose()?;
+    let repeat = cell_contents.next().map(parse_u64).transpose()?;
+    Ok(GridLineCell {
+        text,
+        highlight_id,
+        repeat,
+    })
+}
+
+fn parse_grid_line(mut grid_line_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    // Newer neovim versions append a wrap flag which is not needed for drawing
+    grid_line_arguments.truncate(4);
+    let [grid, row, column_start, cells] = extract_values(
+        grid_line_arguments,
+        [Value::Nil, Value::Nil, Value::Nil, Value::Nil],
+    )?;
+    Ok(RedrawEvent::GridLine {
+        grid: parse_u64(grid)?,
+        row: parse_u64(row)?,
+        column_start: parse_u64(column_start)?,
+        cells: parse_array(cells)?
+            .into_iter()
+            .map(parse_grid_line_cell)
+            .collect::<Result<Vec<_>>>()?,
+    })
+}
+
+#[inline]
+fn parse_clear(clear_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid] = extract_values(clear_arguments, [Value::Nil])?;
+    Ok(RedrawEvent::Clear {
+        grid: parse_u64(grid)?,
+    })
+}
+
+#[inline]
+fn parse_destroy(destroy_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid] = extract_values(destroy_arguments, [Value::Nil])?;
+    Ok(RedrawEvent::Destroy {
+        grid: parse_u64(grid)?,
+    })
+}
+
+fn parse_grid_scroll(grid_scroll_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [grid, top, bottom, left, right, rows, columns] = extract_values(
+        grid_scroll_arguments,
+        [
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+        ],
+    )?;
+    Ok(RedrawEvent::Scroll {
+        grid: parse_u64(grid)?,
+        top: parse_u64(top)?,
+        bottom: parse_u64(bottom)?,
+        left: parse_u64(left)?,
+        right: parse_u64(right)?,
+        rows: parse_i64(rows)?,
+        columns: parse_i64(columns)?,
+    })
+}
+
 #[inline]
 pub fn parse_channel_list(infos: Vec<Value>) -> Result<Vec<ChannelInfo>> {
     infos
@@ -405,7 +871,7 @@ pub fn parse_channel_info(value: Value) -> Result<ChannelInfo> {
     let mut channel_info = ChannelInfo::default();
     for property in channel_map {
         if let (Value::String(name), val) = property {
-            match (name.as_str().unwrap(), val) {
+            match (name.as_str().unwrap_or_default(), val) {
                 ("id", channel_id) => channel_info.id = parse_u64(channel_id)?,
                 ("stream", stream) => channel_info.stream = parse_channel_stream_type(stream)?,
                 ("mode", mode) => channel_info.mode = parse_channel_mode(mode)?,
@@ -452,7 +918,7 @@ fn parse_client_info(client_info_value: Value) -> Result<ClientInfo> {
     let mut client_info = ClientInfo::default();
     for property in client_info_map {
         if let (Value::String(name), value) = property {
-            match (name.as_str().unwrap(), value) {
+            match (name.as_str().unwrap_or_default(), value) {
                 ("name", name) => client_info.name = parse_string(name)?,
                 ("version", version) => client_info.version = parse_client_version(version)?,
                 ("type", client_type) => client_info.client_type = parse_client_type(client_type)?,
@@ -473,7 +939,7 @@ fn parse_client_version(version_value: Value) -> Result<ClientVersion> {
     let mut version = ClientVersion::default();
     for property in version_map {
         if let (Value::String(name), value) = property {
-            match (name.as_str().unwrap(), value) {
+            match (name.as_str().unwrap_or_default(), value) {
                 ("major", major) => version.major = parse_u64(major)?,
                 ("minor", minor) => version.minor = Some(parse_u64(minor)?),
                 ("patch", patch) => version.patch = Some(parse_u64(patch)?),
@@ -558,3 +1024,61 @@ fn extract_values<Arr: AsMut<[Value]>>(values: Vec<Value>, mut arr: Arr) -> Resu
         Ok(arr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redraw_event(name: &str, events: Vec<Vec<Value>>) -> Value {
+        let mut event = vec![Value::from(name)];
+        event.extend(events.into_iter().map(Value::from));
+        Value::from(event)
+    }
+
+    fn grid_line(grid: Value, row: u64) -> Vec<Value> {
+        let cells = vec![Value::from(vec![Value::from("a")])];
+        vec![grid, Value::from(row), Value::from(0), Value::from(cells)]
+    }
+
+    #[test]
+    fn malformed_events_are_skipped_without_dropping_the_batch() {
+        let batch = redraw_event(
+            "grid_line",
+            vec![
+                grid_line(Value::from(1), 0),
+                grid_line(Value::from("not a grid"), 1),
+                vec![Value::from(1)],
+                grid_line(Value::from(1), 2),
+            ],
+        );
+        let rows: Vec<u64> = parse_redraw_event(batch)
+            .unwrap()
+            .into_iter()
+            .map(|event| match event {
+                RedrawEvent::GridLine { row, .. } => row,
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(rows, vec![0, 2]);
+    }
+
+    #[test]
+    fn malformed_batches_are_an_error() {
+        assert!(parse_redraw_event(Value::from("grid_line")).is_err());
+        assert!(parse_redraw_event(Value::from(vec![Value::from(1)])).is_err());
+    }
+
+    #[test]
+    fn negative_linespace_is_treated_as_zero() {
+        let batch = redraw_event(
+            "option_set",
+            vec![vec![Value::from("linespace"), Value::from(-2)]],
+        );
+        match parse_redraw_event(batch).unwrap().as_slice() {
+            [RedrawEvent::OptionSet {
+                gui_option: GuiOption::LineSpace(linespace),
+            }] => assert_eq!(*linespace, 0),
+            events => panic!("unexpected events {:?}", events),
+        }
+    }
+}
@@ -5,27 +5,34 @@ use nvim_rs::Handler;
 use parking_lot::Mutex;
 use tokio::task;
 
-use log::trace;
+use log::{error, trace};
 
 use crate::settings::SETTINGS;
-use crate::{bridge::parse_redraw_event, logging_sender::LoggingUnboundedSender};
+use crate::{
+    bridge::parse_redraw_event, editor::EditorCommand, logging_sender::LoggingUnboundedSender,
+};
 
-use super::{tx_wrapper::TxWrapper, RedrawEvent, UiCommand};
+use super::{
+    clipboard::{handle_clipboard_get, handle_clipboard_set},
+    state_dump::request_state_dump,
+    tx_wrapper::TxWrapper,
+    UiCommand,
+};
 
 #[derive(Clone)]
 pub struct NeovimHandler {
     ui_command_sender: Arc<Mutex<LoggingUnboundedSender<UiCommand>>>,
-    redraw_event_sender: Arc<Mutex<LoggingUnboundedSender<RedrawEvent>>>,
+    editor_command_sender: Arc<Mutex<LoggingUnboundedSender<EditorCommand>>>,
 }
 
 impl NeovimHandler {
     pub fn new(
         ui_command_sender: LoggingUnboundedSender<UiCommand>,
-        redraw_event_sender: LoggingUnboundedSender<RedrawEvent>,
+        editor_command_sender: LoggingUnboundedSender<EditorCommand>,
     ) -> NeovimHandler {
         NeovimHandler {
             ui_command_sender: Arc::new(Mutex::new(ui_command_sender)),
-            redraw_event_sender: Arc::new(Mutex::new(redraw_event_sender)),
+            editor_command_sender: Arc::new(Mutex::new(editor_command_sender)),
         }
     }
 }
@@ -34,6 +41,32 @@ impl NeovimHandler {
 impl Handler for NeovimHandler {
     type Writer = TxWrapper;
 
+    async fn handle_request(
+        &self,
+        event_name: String,
+        args: Vec<nvim_rs::Value>,
+        _neovim: nvim_rs::Neovim<TxWrapper>,
+    ) -> Result<nvim_rs::Value, nvim_rs::Value> {
+        trace!("Neovim request: {:?}", &event_name);
+        if event_name == "xvim.dump_state" {
+            let editor_command_sender = self.editor_command_sender.lock().clone();
+            return request_state_dump(&editor_command_sender)
+                .await
+                .map(nvim_rs::Value::from)
+                .ok_or_else(|| nvim_rs::Value::from("Could not dump the editor state"));
+        }
+        task::spawn_blocking(move || match event_name.as_ref() {
+            "xvim.clipboard_get" => handle_clipboard_get(args),
+            "xvim.clipboard_set" => handle_clipboard_set(args),
+            _ => Err(nvim_rs::Value::from(format!(
+                "Unknown request {}",
+                event_name
+            ))),
+        })
+        .await
+        .unwrap_or_else(|join_error| Err(nvim_rs::Value::from(join_error.to_string())))
+    }
+
     async fn handle_notify(
         &self,
         event_name: String,
@@ -41,14 +74,22 @@ impl Handler for NeovimHandler {
         _neovim: nvim_rs::Neovim<TxWrapper>,
     ) {
         trace!("Neovim nofification: {:?}", &event_name);
-        let redraw_event_sender = self.redraw_event_sender.clone();
+        let editor_command_sender = self.editor_command_sender.clone();
         task::spawn_blocking(move || match event_name.as_ref() {
             "redraw" => {
                 for events in args {
-                    let parsed_events = parse_redraw_event(events).expect("parsed redraw event");
+                    let parsed_events = match parse_redraw_event(events) {
+                        Ok(parsed_events) => parsed_events,
+                        Err(error) => {
+                            error!("Skipped redraw event: {}", error);
+                            continue;
+                        }
+                    };
                     for parsed_event in parsed_events {
-                        let redraw_event_sender = redraw_event_sender.lock();
-                        redraw_event_sender.send(parsed_event).ok();
+                        let editor_command_sender = editor_command_sender.lock();
+                        editor_command_sender
+                            .send(EditorCommand::RedrawEvent(parsed_event))
+                            .ok();
                     }
                 }
             }
@@ -1,21 +1,35 @@
+mod clipboard;
 mod create;
 mod events;
 mod handler;
+mod paste;
+mod state_dump;
 mod tx_wrapper;
 mod ui_commands;
 
+pub use clipboard::*;
 pub use events::*;
 pub use handler::*;
+pub use paste::*;
 pub use tx_wrapper::*;
 pub use ui_commands::*;
 
-use self::create::create_nvim_commad;
+use self::{create::create_nvim_commad, state_dump::write_state_dump};
 use crate::settings::SETTINGS;
-use crate::{cmd_line::CmdLineSettings, logging_sender::LoggingUnboundedSender};
-use log::{error, info, trace};
+use crate::{
+    cmd_line::CmdLineSettings, editor::EditorCommand, logging_sender::LoggingUnboundedSender,
+    window::Dimensions,
+};
+use log::{error, info, warn};
 use nvim_rs::{UiAttachOptions, Value};
-use std::sync::{atomic::AtomicBool, Arc};
-use tokio::{runtime::Runtime, sync::mpsc::UnboundedReceiver};
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
+use tokio::{
+    runtime::Runtime,
+    sync::{mpsc::UnboundedReceiver, oneshot},
+};
 
 pub struct Bridge {
     _runtime: Runtime,
@@ -24,14 +38,20 @@ pub struct Bridge {
 pub fn start_bridge(
     ui_command_sender: LoggingUnboundedSender<UiCommand>,
     ui_command_receiver: UnboundedReceiver<UiCommand>,
-    redraw_event_sender: LoggingUnboundedSender<RedrawEvent>,
+    editor_command_sender: LoggingUnboundedSender<EditorCommand>,
+    grid_size_receiver: oneshot::Receiver<Dimensions>,
     running: Arc<AtomicBool>,
 ) -> Bridge {
     let runtime = Runtime::new().unwrap();
+    #[cfg(unix)]
+    runtime.spawn(state_dump::dump_state_on_signal(
+        editor_command_sender.clone(),
+    ));
     runtime.spawn(start_neovim_runtime(
         ui_command_sender,
         ui_command_receiver,
-        redraw_event_sender,
+        editor_command_sender,
+        grid_size_receiver,
         running,
     ));
     Bridge { _runtime: runtime }
@@ -39,17 +59,19 @@ pub fn start_bridge(
 
 async fn start_neovim_runtime(
     ui_command_sender: LoggingUnboundedSender<UiCommand>,
-    mut ui_command_receiver: UnboundedReceiver<UiCommand>,
-    redraw_event_sender: LoggingUnboundedSender<RedrawEvent>,
+    ui_command_receiver: UnboundedReceiver<UiCommand>,
+    editor_command_sender: LoggingUnboundedSender<EditorCommand>,
+    grid_size_receiver: oneshot::Receiver<Dimensions>,
     running: Arc<AtomicBool>,
 ) {
-    let handler = NeovimHandler::new(ui_command_sender.clone(), redraw_event_sender.clone());
+    let handler = NeovimHandler::new(ui_command_sender.clone(), editor_command_sender.clone());
     let (nvim, io_handler) = create::new_child_cmd(&mut create_nvim_commad(), handler)
         .await
         .expect("Could not locate or start neovim process");
     if nvim.get_api_info().await.is_err() {
         error!("Cannot get neovim api info, either neovide is launched with an unknown command line option or neovim version not supported!");
     }
+    let ui_editor_command_sender = editor_command_sender.clone();
     let close_wathcer_running = running.clone();
     tokio::spawn(async move {
         info!("Close watcher started");
@@ -62,6 +84,10 @@ async fn start_neovim_runtime(
             }
             Ok(Ok(())) => {}
         }
+        // The window exits the process as soon as running is cleared
+        if let Some(path) = SETTINGS.get::<CmdLineSettings>().dump_state {
+            write_state_dump(&editor_command_sender, Path::new(&path)).await;
+        }
         close_wathcer_running.store(false, std::sync::atomic::Ordering::Relaxed);
     });
 
@@ -117,6 +143,10 @@ async fn start_neovim_runtime(
 
     info!("Xvim registered to nvim with channel id {}", xvim_channel);
 
+    if xvim_channel != 0 {
+        register_clipboard_provider(&nvim, xvim_channel).await;
+    }
+
     nvim.set_option("lazydraw", Value::Boolean(false))
         .await
         .ok();
@@ -124,40 +154,28 @@ async fn start_neovim_runtime(
         .await
         .ok();
 
-    let setting = SETTINGS.get::<CmdLineSettings>();
-    let geometry = setting.geometry;
+    let grid_size = grid_size_receiver.await.unwrap_or_else(|_| {
+        warn!("Window did not report a grid size, attaching with 80x24");
+        Dimensions {
+            width: 80,
+            height: 24,
+        }
+    });
     let mut options = UiAttachOptions::new();
     options.set_linegrid_external(true);
-    options.set_multigrid_external(setting.multi_grid);
-    nvim.ui_attach(geometry.width as i64, geometry.height as i64, &options)
+    options.set_multigrid_external(SETTINGS.get::<CmdLineSettings>().multi_grid);
+    nvim.ui_attach(grid_size.width as i64, grid_size.height as i64, &options)
         .await
         .expect("Could not attach ui to neovim process");
     info!("Neovim process attached");
     let nvim = Arc::new(nvim);
 
-    let ui_command_running = running.clone();
-    let input_nvim = nvim.clone();
-    tokio::spawn(async move {
-        loop {
-            if !ui_command_running.load(std::sync::atomic::Ordering::Relaxed) {
-                break;
-            }
-
-            match ui_command_receiver.recv().await {
-                Some(ui_commands) => {
-                    let input_nvim = input_nvim.clone();
-                    tokio::spawn(async move {
-                        ui_commands.execute(&input_nvim).await;
-                    });
-                }
-                None => {
-                    ui_command_running.store(false, std::sync::atomic::Ordering::Relaxed);
-                    trace!("stop execute ui_command");
-                    break;
-                }
-            }
-        }
-    });
+    tokio::spawn(execute_ui_commands(
+        ui_command_receiver,
+        nvim.clone(),
+        ui_editor_command_sender,
+        running.clone(),
+    ));
     SETTINGS.read_initial_values(&nvim).await;
     SETTINGS.setup_changed_listeners(&nvim).await;
 }
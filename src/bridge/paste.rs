@@ -0,0 +1,261 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use log::{error, info, trace};
+use nvim_rs::Neovim;
+
+use super::{
+    clipboard::{ClipboardRegister, CLIPBOARD},
+    tx_wrapper::TxWrapper,
+};
+
+const PASTE_CHUNK_SIZE: usize = 64 * 1024;
+
+lazy_static! {
+    pub static ref PASTE_PROGRESS: PasteProgress = PasteProgress::new();
+}
+
+pub struct PasteProgress {
+    active: AtomicBool,
+    cancel_requested: AtomicBool,
+    total: AtomicUsize,
+    sent: AtomicUsize,
+}
+
+impl PasteProgress {
+    fn new() -> Self {
+        PasteProgress {
+            active: AtomicBool::new(false),
+            cancel_requested: AtomicBool::new(false),
+            total: AtomicUsize::new(0),
+            sent: AtomicUsize::new(0),
+        }
+    }
+
+    fn start(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.sent.store(0, Ordering::Relaxed);
+        self.cancel_requested.store(false, Ordering::Relaxed);
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    fn advance(&self, sent: usize) {
+        self.sent.fetch_add(sent, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {
+        self.active.store(false, Ordering::Relaxed);
+        self.cancel_requested.store(false, Ordering::Relaxed);
+    }
+
+    fn take_cancel_request(&self) -> bool {
+        self.cancel_requested.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn request_cancel(&self) {
+        if self.is_active() {
+            self.cancel_requested.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn percentage(&self) -> Option<u64> {
+        if !self.is_active() {
+            return None;
+        }
+        let total = self.total.load(Ordering::Relaxed).max(1);
+        let sent = self.sent.load(Ordering::Relaxed).min(total);
+        Some((sent * 100 / total) as u64)
+    }
+}
+
+fn split_chunks(text: &str, chunk_size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > chunk_size {
+        let mut split = chunk_size;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        // A chunk holds at least one character, even one wider than the chunk size
+        if split == 0 {
+            split = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, remaining) = rest.split_at(split);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+    if !rest.is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+fn paste_phase(index: usize, chunk_count: usize) -> i64 {
+    if chunk_count == 1 {
+        -1
+    } else if index == 0 {
+        1
+    } else if index + 1 == chunk_count {
+        3
+    } else {
+        2
+    }
+}
+
+pub async fn stream_paste(nvim: &Neovim<TxWrapper>) {
+    let text =
+        match tokio::task::spawn_blocking(|| CLIPBOARD.lock().get_text(ClipboardRegister::Plus))
+            .await
+        {
+            Ok(Ok(text)) => text,
+            Ok(Err(error)) => {
+                error!("Could not read clipboard: {}", error);
+                return;
+            }
+            Err(join_error) => {
+                error!("Clipboard read failed: {}", join_error);
+                return;
+            }
+        };
+    if text.is_empty() {
+        return;
+    }
+
+    let chunks = split_chunks(&text, PASTE_CHUNK_SIZE);
+    trace!("Pasting {} bytes in {} chunks", text.len(), chunks.len());
+    send_chunks(&chunks, &PASTE_PROGRESS, |chunk, phase| async move {
+        nvim.paste(&chunk, true, phase).await
+    })
+    .await;
+}
+
+/// Pastes the chunks one phase after another, ending the paste early when it is cancelled
+async fn send_chunks<F, R, E>(chunks: &[&str], progress: &PasteProgress, mut paste: F)
+where
+    F: FnMut(String, i64) -> R,
+    R: Future<Output = Result<bool, E>>,
+    E: Display,
+{
+    progress.start(chunks.iter().map(|chunk| chunk.len()).sum());
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 && progress.take_cancel_request() {
+            info!("Paste cancelled after {} of {} chunks", index, chunks.len());
+            paste(String::new(), 3).await.ok();
+            break;
+        }
+        match paste(chunk.to_string(), paste_phase(index, chunks.len())).await {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("Neovim cancelled the paste");
+                break;
+            }
+            Err(error) => {
+                error!("Paste failed: {}", error);
+                break;
+            }
+        }
+        progress.advance(chunk.len());
+        tokio::task::yield_now().await;
+    }
+    progress.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, future::ready};
+
+    type Calls = RefCell<Vec<(String, i64)>>;
+
+    async fn paste_all(chunks: &[&str], progress: &PasteProgress, calls: &Calls) {
+        send_chunks(chunks, progress, |chunk, phase| {
+            calls.borrow_mut().push((chunk, phase));
+            ready(Ok::<_, String>(true))
+        })
+        .await;
+    }
+
+    fn phases(calls: Calls) -> Vec<i64> {
+        calls
+            .into_inner()
+            .into_iter()
+            .map(|(_, phase)| phase)
+            .collect()
+    }
+
+    #[test]
+    fn chunks_split_at_character_boundaries() {
+        let text = "aé中😀b".repeat(50);
+        for chunk_size in 1..12 {
+            let chunks = split_chunks(&text, chunk_size);
+            assert_eq!(chunks.concat(), text);
+            for chunk in &chunks[..chunks.len() - 1] {
+                // A character wider than the chunk size gets a chunk of its own
+                assert!(chunk.len() <= chunk_size.max(4), "{:?}", chunk);
+                assert!(!chunk.is_empty());
+            }
+        }
+        assert_eq!(split_chunks("abc", 3), vec!["abc"]);
+        assert_eq!(split_chunks("abcd", 3), vec!["abc", "d"]);
+        assert_eq!(split_chunks("a中", 2), vec!["a", "中"]);
+    }
+
+    #[test]
+    fn phases_follow_the_chunk_position() {
+        assert_eq!(paste_phase(0, 1), -1);
+        let sequence: Vec<_> = (0..4).map(|index| paste_phase(index, 4)).collect();
+        assert_eq!(sequence, vec![1, 2, 2, 3]);
+        assert_eq!(vec![paste_phase(0, 2), paste_phase(1, 2)], vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn chunks_are_sent_in_phases() {
+        let progress = PasteProgress::new();
+        let calls = Calls::default();
+        paste_all(&["a"], &progress, &calls).await;
+        assert_eq!(calls.into_inner(), vec![("a".to_owned(), -1)]);
+
+        let calls = Calls::default();
+        paste_all(&["a", "b", "c"], &progress, &calls).await;
+        assert_eq!(phases(calls), vec![1, 2, 3]);
+        assert!(!progress.is_active());
+    }
+
+    #[tokio::test]
+    async fn cancelling_ends_the_paste_early() {
+        let progress = PasteProgress::new();
+        let calls = Calls::default();
+        send_chunks(&["a", "b", "c"], &progress, |chunk, phase| {
+            calls.borrow_mut().push((chunk, phase));
+            progress.request_cancel();
+            ready(Ok::<_, String>(true))
+        })
+        .await;
+        assert_eq!(
+            calls.into_inner(),
+            vec![("a".to_owned(), 1), (String::new(), 3)]
+        );
+        assert!(!progress.is_active());
+        assert_eq!(progress.percentage(), None);
+    }
+
+    #[tokio::test]
+    async fn neovim_refusing_a_chunk_stops_the_paste() {
+        let progress = PasteProgress::new();
+        let calls = Calls::default();
+        send_chunks(&["a", "b", "c"], &progress, |chunk, phase| {
+            calls.borrow_mut().push((chunk, phase));
+            ready(Ok::<_, String>(false))
+        })
+        .await;
+        assert_eq!(phases(calls), vec![1]);
+        assert!(!progress.is_active());
+    }
+}
@@ -0,0 +1,54 @@
+use std::{path::Path, time::Duration};
+
+use log::{error, info};
+use tokio::{sync::oneshot, time::timeout};
+
+use crate::{editor::EditorCommand, logging_sender::LoggingUnboundedSender};
+
+const DUMP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Asks the editor thread for a json dump of its model
+pub async fn request_state_dump(
+    editor_command_sender: &LoggingUnboundedSender<EditorCommand>,
+) -> Option<String> {
+    let (state_sender, state_receiver) = oneshot::channel();
+    editor_command_sender
+        .send(EditorCommand::DumpState(state_sender))
+        .ok()?;
+    timeout(DUMP_TIMEOUT, state_receiver).await.ok()?.ok()
+}
+
+pub async fn write_state_dump(
+    editor_command_sender: &LoggingUnboundedSender<EditorCommand>,
+    path: &Path,
+) {
+    match request_state_dump(editor_command_sender).await {
+        Some(state) => match tokio::fs::write(path, state).await {
+            Ok(()) => info!("Editor state written to {}", path.display()),
+            Err(error) => error!(
+                "Could not write the editor state to {}: {}",
+                path.display(),
+                error
+            ),
+        },
+        None => error!("The editor did not answer the state dump request"),
+    }
+}
+
+/// Writes a dump to the temp directory every time the process receives SIGUSR1
+#[cfg(unix)]
+pub async fn dump_state_on_signal(editor_command_sender: LoggingUnboundedSender<EditorCommand>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut user_signal = match signal(SignalKind::user_defined1()) {
+        Ok(user_signal) => user_signal,
+        Err(error) => {
+            error!("Could not listen for SIGUSR1: {}", error);
+            return;
+        }
+    };
+    let path = std::env::temp_dir().join(format!("xvim-state-{}.json", std::process::id()));
+    while user_signal.recv().await.is_some() {
+        write_state_dump(&editor_command_sender, &path).await;
+    }
+}
@@ -1,23 +1,439 @@
-use super::tx_wrapper::TxWrapper;
-use nvim_rs::Neovim;
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{paste::stream_paste, tx_wrapper::TxWrapper};
+use crate::{
+    cmd_line::CmdLineSettings, editor::EditorCommand, logging_sender::LoggingUnboundedSender,
+    settings::SETTINGS,
+};
+use log::{error, trace, warn};
+use nvim_rs::{error::CallError, Neovim, Value};
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, oneshot},
+    time::timeout,
+};
+
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// The quit is scheduled so the request returns before any confirm prompt blocks neovim.
+// Otherwise the prompt would wait for keyboard input queued behind this very request.
+const QUIT_LUA: &str = r#"
+vim.schedule(function()
+  if type(_G.xvim_close_requested) == 'function' then
+    _G.xvim_close_requested()
+  else
+    vim.cmd('confirm qa')
+  end
+end)
+"#;
+
+// Input typed into the main OS window must not keep going to an external window
+const FOCUS_MAIN_WINDOW_LUA: &str = r#"
+if vim.api.nvim_win_get_config(0).external then
+  vim.cmd('wincmd p')
+end
+"#;
 
 #[derive(Debug, Clone)]
 pub enum UiCommand {
     Quit,
-    Resize { width: u64, height: u64 },
+    ForceQuit,
+    Resize {
+        width: u64,
+        height: u64,
+    },
+    Keyboard(String),
+    Paste,
+    ResizeGrid {
+        grid: u64,
+        width: u64,
+        height: u64,
+    },
+    FocusWindow(u64),
+    FocusMainWindow,
+    CloseWindow(u64),
+    /// A mouse event at a cell of an external `grid`, or without one at a cell of the main
+    /// window, which is mapped to the grid under it
+    Mouse {
+        button: String,
+        action: String,
+        modifiers: String,
+        grid: Option<u64>,
+        position: (u64, u64),
+    },
 }
 
 impl UiCommand {
-    pub async fn execute(self, nvim: &Neovim<TxWrapper>) {
+    pub async fn execute(
+        self,
+        nvim: &Neovim<TxWrapper>,
+        editor_command_sender: &LoggingUnboundedSender<EditorCommand>,
+    ) -> Result<(), Box<CallError>> {
         match self {
             UiCommand::Quit => {
-                nvim.command("qa!").await.ok();
+                quit(nvim.exec_lua(QUIT_LUA, vec![])).await?;
+            }
+            UiCommand::ForceQuit => {
+                force_quit(nvim.command("qa!"), || std::process::exit(0)).await;
             }
             UiCommand::Resize { width, height } => {
                 nvim.ui_try_resize(width.max(10) as i64, height.max(3) as i64)
-                    .await
-                    .expect("Resize faild");
+                    .await?;
+            }
+            UiCommand::Keyboard(input) => {
+                nvim.input(&input).await?;
+            }
+            UiCommand::Paste => {
+                stream_paste(nvim).await;
+            }
+            UiCommand::ResizeGrid {
+                grid,
+                width,
+                height,
+            } => {
+                nvim.ui_try_resize_grid(grid as i64, width.max(1) as i64, height.max(1) as i64)
+                    .await?;
+            }
+            UiCommand::FocusWindow(window) => {
+                nvim.call_function("win_gotoid", vec![Value::from(window)])
+                    .await?;
+            }
+            UiCommand::FocusMainWindow => {
+                nvim.exec_lua(FOCUS_MAIN_WINDOW_LUA, vec![]).await?;
+            }
+            UiCommand::CloseWindow(window) => {
+                nvim.call_function(
+                    "nvim_win_close",
+                    vec![Value::from(window), Value::from(false)],
+                )
+                .await?;
+            }
+            UiCommand::Mouse {
+                button,
+                action,
+                modifiers,
+                grid,
+                position,
+            } => {
+                // Without multigrid neovim resolves the window under the mouse by itself
+                let target = match grid {
+                    Some(grid) => Some((grid, position)),
+                    None if SETTINGS.get::<CmdLineSettings>().multi_grid => {
+                        request_grid_at(editor_command_sender, position).await
+                    }
+                    None => Some((0, position)),
+                };
+                if let Some((grid, (column, row))) = target {
+                    nvim.input_mouse(
+                        &button,
+                        &action,
+                        &modifiers,
+                        grid as i64,
+                        row as i64,
+                        column as i64,
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Asks the editor thread which grid is under a screen cell
+async fn request_grid_at(
+    editor_command_sender: &LoggingUnboundedSender<EditorCommand>,
+    (column, row): (u64, u64),
+) -> Option<(u64, (u64, u64))> {
+    let (grid_sender, grid_receiver) = oneshot::channel();
+    editor_command_sender
+        .send(EditorCommand::GridAt(
+            (column as f64, row as f64),
+            grid_sender,
+        ))
+        .ok()?;
+    grid_receiver.await.ok()?
+}
+
+/// Waits for neovim to answer the quit `request`, giving up after `QUIT_TIMEOUT`
+async fn quit<R, T, E>(request: R) -> Result<(), E>
+where
+    R: Future<Output = Result<T, E>>,
+{
+    match timeout(QUIT_TIMEOUT, request).await {
+        Ok(result) => result.map(|_| ()),
+        Err(_) => {
+            // Neovim may be busy with modified buffers, quitting anyway would lose them
+            warn!("Neovim did not answer the quit request, close the window again to force quit");
+            Ok(())
+        }
+    }
+}
+
+/// Sends the `qa!` request, calling `exit` when neovim does not answer it in time
+async fn force_quit<R: Future>(request: R, exit: impl FnOnce()) {
+    if timeout(QUIT_TIMEOUT, request).await.is_err() {
+        // Neovim exits by itself once its stdin closes with our process
+        error!("Neovim is not responding, exiting without it");
+        exit();
+    }
+}
+
+/// Merges commands that can be sent to neovim as one call without changing their effect.
+/// Consecutive keyboard input is joined into a single string and of consecutive resizes
+/// only the last is kept. Nothing is moved past another command.
+pub fn coalesce_ui_commands(commands: Vec<UiCommand>) -> Vec<UiCommand> {
+    let mut coalesced: Vec<UiCommand> = Vec::with_capacity(commands.len());
+    for command in commands {
+        match (coalesced.last_mut(), command) {
+            (Some(UiCommand::Keyboard(input)), UiCommand::Keyboard(next_input)) => {
+                input.push_str(&next_input);
+            }
+            (Some(last @ UiCommand::Resize { .. }), resize @ UiCommand::Resize { .. }) => {
+                *last = resize;
+            }
+            (_, command) => coalesced.push(command),
+        }
+    }
+    coalesced
+}
+
+/// Executes ui commands one after another in the order they were sent
+pub async fn execute_ui_commands(
+    ui_command_receiver: UnboundedReceiver<UiCommand>,
+    nvim: Arc<Neovim<TxWrapper>>,
+    editor_command_sender: LoggingUnboundedSender<EditorCommand>,
+    running: Arc<AtomicBool>,
+) {
+    run_ui_commands(ui_command_receiver, running, |ui_command| {
+        ui_command.execute(&nvim, &editor_command_sender)
+    })
+    .await;
+}
+
+/// Runs every received command through `execute`, an error only being logged
+async fn run_ui_commands<F, R, E>(
+    mut ui_command_receiver: UnboundedReceiver<UiCommand>,
+    running: Arc<AtomicBool>,
+    mut execute: F,
+) where
+    F: FnMut(UiCommand) -> R,
+    R: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    while let Some(ui_command) = ui_command_receiver.recv().await {
+        if !running.load(Ordering::Relaxed) {
+            break;
+        }
+        let mut ui_commands = vec![ui_command];
+        while let Ok(ui_command) = ui_command_receiver.try_recv() {
+            ui_commands.push(ui_command);
+        }
+        for ui_command in coalesce_ui_commands(ui_commands) {
+            let description = format!("{:?}", ui_command);
+            if let Err(error) = execute(ui_command).await {
+                error!("Failed to execute {}: {}", description, error);
             }
         }
     }
+    running.store(false, Ordering::Relaxed);
+    trace!("stop execute ui_command");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::{Cell, RefCell},
+        future::{pending, ready},
+    };
+    use tokio::{sync::mpsc::unbounded_channel, time::Instant};
+
+    fn keyboard(input: &str) -> UiCommand {
+        UiCommand::Keyboard(input.to_owned())
+    }
+
+    fn resize(width: u64, height: u64) -> UiCommand {
+        UiCommand::Resize { width, height }
+    }
+
+    fn mouse(action: &str, position: (u64, u64)) -> UiCommand {
+        UiCommand::Mouse {
+            button: "left".to_owned(),
+            action: action.to_owned(),
+            modifiers: String::new(),
+            grid: None,
+            position,
+        }
+    }
+
+    fn describe(commands: Vec<UiCommand>) -> Vec<String> {
+        commands
+            .into_iter()
+            .map(|command| format!("{:?}", command))
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_keyboard_input_is_joined() {
+        let commands = vec![keyboard("a"), keyboard("<CR>"), keyboard("b")];
+        assert_eq!(
+            describe(coalesce_ui_commands(commands)),
+            describe(vec![keyboard("a<CR>b")])
+        );
+    }
+
+    #[test]
+    fn only_the_last_of_consecutive_resizes_is_kept() {
+        let commands = vec![resize(80, 24), resize(90, 30), resize(100, 40)];
+        assert_eq!(
+            describe(coalesce_ui_commands(commands)),
+            describe(vec![resize(100, 40)])
+        );
+    }
+
+    #[test]
+    fn resizes_are_not_moved_past_input() {
+        let commands = vec![
+            resize(80, 24),
+            keyboard("i"),
+            mouse("press", (3, 4)),
+            resize(90, 30),
+            resize(100, 40),
+            keyboard("x"),
+            mouse("release", (3, 4)),
+            keyboard("y"),
+        ];
+        assert_eq!(
+            describe(coalesce_ui_commands(commands)),
+            describe(vec![
+                resize(80, 24),
+                keyboard("i"),
+                mouse("press", (3, 4)),
+                resize(100, 40),
+                keyboard("x"),
+                mouse("release", (3, 4)),
+                keyboard("y"),
+            ])
+        );
+    }
+
+    #[test]
+    fn ordering_holds_under_load() {
+        let mut commands = Vec::new();
+        let mut expected = Vec::new();
+        for index in 0..1000u64 {
+            let input = format!("<{}>", index);
+            match index % 4 {
+                0 => {
+                    commands.push(keyboard(&input));
+                    commands.push(keyboard("a"));
+                    expected.push(keyboard(&format!("{}a", input)));
+                }
+                1 => {
+                    commands.push(resize(index, 1));
+                    commands.push(resize(index, 2));
+                    expected.push(resize(index, 2));
+                }
+                2 => {
+                    commands.push(mouse("press", (index, 0)));
+                    expected.push(mouse("press", (index, 0)));
+                }
+                _ => {
+                    commands.push(mouse("drag", (index, 1)));
+                    commands.push(mouse("drag", (index, 2)));
+                    expected.push(mouse("drag", (index, 1)));
+                    expected.push(mouse("drag", (index, 2)));
+                }
+            }
+        }
+        assert_eq!(describe(coalesce_ui_commands(commands)), describe(expected));
+    }
+
+    fn run_mock_executor(commands: Vec<UiCommand>, running: bool) -> (Vec<String>, bool) {
+        let (sender, receiver) = unbounded_channel();
+        for command in commands {
+            sender.send(command).unwrap();
+        }
+        drop(sender);
+        let running = Arc::new(AtomicBool::new(running));
+        let executed = RefCell::new(Vec::new());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(run_ui_commands(receiver, running.clone(), |command| {
+            let failed = matches!(command, UiCommand::Resize { .. });
+            executed.borrow_mut().push(command);
+            ready(if failed { Err("mock failure") } else { Ok(()) })
+        }));
+        (
+            describe(executed.into_inner()),
+            running.load(Ordering::Relaxed),
+        )
+    }
+
+    #[test]
+    fn the_executor_keeps_the_order_and_continues_after_errors() {
+        let commands = vec![
+            keyboard("a"),
+            keyboard("b"),
+            resize(80, 24),
+            mouse("press", (1, 2)),
+            resize(90, 30),
+            keyboard("c"),
+            UiCommand::Paste,
+        ];
+        let (executed, running) = run_mock_executor(commands, true);
+        assert_eq!(
+            executed,
+            describe(vec![
+                keyboard("ab"),
+                resize(80, 24),
+                mouse("press", (1, 2)),
+                resize(90, 30),
+                keyboard("c"),
+                UiCommand::Paste,
+            ])
+        );
+        assert!(!running, "stopped once the channel closed");
+    }
+
+    #[test]
+    fn the_executor_stops_once_not_running() {
+        let (executed, running) = run_mock_executor(vec![keyboard("a")], false);
+        assert!(executed.is_empty());
+        assert!(!running);
+    }
+
+    #[tokio::test]
+    async fn quit_reports_neovim_errors() {
+        assert_eq!(quit(ready(Ok::<_, &str>(Value::Nil))).await, Ok(()));
+        assert_eq!(quit(ready(Err::<Value, _>("failed"))).await, Err("failed"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quit_gives_up_on_a_busy_neovim_without_exiting() {
+        let start = Instant::now();
+        assert_eq!(quit(pending::<Result<Value, &str>>()).await, Ok(()));
+        assert_eq!(start.elapsed(), QUIT_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn force_quit_exits_when_neovim_does_not_answer() {
+        let exited = Cell::new(false);
+        force_quit(ready(()), || exited.set(true)).await;
+        assert!(!exited.get());
+
+        let start = Instant::now();
+        force_quit(pending::<()>(), || exited.set(true)).await;
+        assert!(exited.get());
+        assert_eq!(start.elapsed(), QUIT_TIMEOUT);
+    }
 }
@@ -14,6 +14,7 @@ pub struct CmdLineSettings {
     pub geometry: Dimensions,
     pub multi_grid: bool,
     pub maximized: bool,
+    pub dump_state: Option<String>,
 }
 
 impl Default for CmdLineSettings {
@@ -28,6 +29,7 @@ impl Default for CmdLineSettings {
             geometry: Dimensions::default(),
             multi_grid: false,
             maximized: false,
+            dump_state: None,
         }
     }
 }
@@ -81,6 +83,12 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
             Arg::with_name("maximized")
                 .long("maximized")
                 .help("Maxmize the window"),
+        )
+        .arg(
+            Arg::with_name("dump_state")
+                .long("dump-state")
+                .takes_value(true)
+                .help("Write the editor state as json to this file when neovim exits"),
         );
 
     let matches = clapp.get_matches();
@@ -100,6 +108,7 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
         geometry: parse_window_geometry(matches.value_of("geometry").map(|i| i.to_owned()))?,
         multi_grid: std::env::var("XVIM_MULTIGRID").is_ok() || matches.is_present("multi_grid"),
         maximized: std::env::var("XVIM_MAXIMIZED").is_ok() || matches.is_present("maximized"),
+        dump_state: matches.value_of("dump_state").map(String::from),
     });
     Ok(())
 }
@@ -1,7 +1,9 @@
+use serde::Serialize;
+use std::collections::HashMap;
 use std::sync::Arc;
 use super::style::Style;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum CursorShape {
     Block,
     Horizontal,
@@ -18,7 +20,7 @@ impl CursorShape {
         }
     }
 }
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 pub struct CursorMode {
     pub shape: Option<CursorShape>,
     pub style_id: Option<u64>,
@@ -28,7 +30,7 @@ pub struct CursorMode {
     pub blinkoff: Option<u64>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Cursor {
     pub grid_position: (u64, u64),
     pub parent_window_id: u64,
@@ -59,4 +61,25 @@ impl Cursor {
             character: " ".to_string(),
         }
     }
-}
\ No newline at end of file
+
+    /// Applies the shape, size, blink timings and highlight of a mode from mode_info_set
+    pub fn change_mode(&mut self, cursor_mode: &CursorMode, styles: &HashMap<u64, Arc<Style>>) {
+        let CursorMode {
+            shape,
+            style_id,
+            cell_percentage,
+            blinkwait,
+            blinkon,
+            blinkoff,
+        } = cursor_mode;
+
+        if let Some(shape) = shape {
+            self.shape = shape.clone();
+        }
+        self.style = style_id.and_then(|style_id| styles.get(&style_id).cloned());
+        self.cell_percentage = *cell_percentage;
+        self.blinkwait = *blinkwait;
+        self.blinkon = *blinkon;
+        self.blinkoff = *blinkoff;
+    }
+}
@@ -1,8 +1,12 @@
-use std::{cell::RefCell, sync::mpsc::SendError};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::mpsc::SendError,
+};
 
 use crate::logging_sender::LoggingBoundedSender;
 
-use super::DrawCommand;
+use super::{DrawCommand, WindowDrawCommand};
 
 pub struct DrawCommandBatcher {
     batched_draw_commander: LoggingBoundedSender<Vec<DrawCommand>>,
@@ -17,14 +21,160 @@ impl DrawCommandBatcher {
         }
     }
 
+    /// Buffers a command until the next flush
     pub fn queue(&self, draw_comand: DrawCommand) -> Result<(), SendError<Vec<DrawCommand>>> {
         self.draw_comands.borrow_mut().push(draw_comand);
-        let commands = self.draw_comands.replace(Vec::new());
+        Ok(())
+    }
+
+    /// Sends everything queued since the last flush as a single batch
+    pub fn send_batch(&self) -> Result<(), SendError<Vec<DrawCommand>>> {
+        let commands = coalesce_draw_commands(self.draw_comands.replace(Vec::new()));
         if !commands.is_empty() {
             self.batched_draw_commander.send(commands)
         } else {
             Ok(())
         }
     }
+}
+
+/// Drops commands which a later command in the same batch makes redundant.
+/// A grid line is superseded by a later update of the same line unless the grid was
+/// scrolled, cleared, moved or closed in between, and only the last cursor update is kept.
+fn coalesce_draw_commands(commands: Vec<DrawCommand>) -> Vec<DrawCommand> {
+    let mut seen_lines: HashMap<u64, HashSet<u64>> = HashMap::new();
+    let mut seen_cursor = false;
+    let mut coalesced: Vec<DrawCommand> = Vec::with_capacity(commands.len());
+
+    for command in commands.into_iter().rev() {
+        let keep = match &command {
+            DrawCommand::Window {
+                grid_id,
+                command: WindowDrawCommand::Line { row, .. },
+            } => seen_lines.entry(*grid_id).or_default().insert(*row),
+            DrawCommand::Window { grid_id, .. } | DrawCommand::CloseWindow(grid_id) => {
+                seen_lines.remove(grid_id);
+                true
+            }
+            DrawCommand::UpdateCursor(_) => !std::mem::replace(&mut seen_cursor, true),
+            _ => true,
+        };
+        if keep {
+            coalesced.push(command);
+        }
+    }
+
+    coalesced.reverse();
+    coalesced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{Cursor, GridCell};
+
+    fn line(grid_id: u64, row: u64, text: &str) -> DrawCommand {
+        let cells = vec![GridCell {
+            text: text.to_owned(),
+            ..GridCell::empty()
+        }];
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::Line { row, cells },
+        }
+    }
+
+    fn window(grid_id: u64, command: WindowDrawCommand) -> DrawCommand {
+        DrawCommand::Window { grid_id, command }
+    }
 
+    fn scroll(grid_id: u64) -> DrawCommand {
+        window(
+            grid_id,
+            WindowDrawCommand::Scroll {
+                top: 0,
+                bottom: 10,
+                left: 0,
+                right: 10,
+                rows: 1,
+                columns: 0,
+            },
+        )
+    }
+
+    fn cursor(column: u64) -> DrawCommand {
+        let mut cursor = Cursor::new();
+        cursor.grid_position = (column, 0);
+        DrawCommand::UpdateCursor(cursor)
+    }
+
+    fn describe(commands: Vec<DrawCommand>) -> Vec<String> {
+        commands
+            .into_iter()
+            .map(|command| format!("{:?}", command))
+            .collect()
+    }
+
+    fn assert_coalesced(commands: Vec<DrawCommand>, expected: Vec<DrawCommand>) {
+        assert_eq!(
+            describe(coalesce_draw_commands(commands)),
+            describe(expected)
+        );
+    }
+
+    #[test]
+    fn superseded_lines_are_dropped() {
+        assert_coalesced(
+            vec![line(1, 0, "a"), line(1, 1, "b"), line(1, 0, "c")],
+            vec![line(1, 1, "b"), line(1, 0, "c")],
+        );
+    }
+
+    #[test]
+    fn grid_changes_keep_earlier_lines() {
+        let barriers = vec![
+            scroll(1),
+            window(1, WindowDrawCommand::Clear),
+            window(1, WindowDrawCommand::Hide),
+            window(1, WindowDrawCommand::Viewport { scroll_delta: 1.0 }),
+            window(
+                1,
+                WindowDrawCommand::Position {
+                    grid_position: (0.0, 0.0),
+                    width: 10,
+                    height: 10,
+                    floating_order: None,
+                    external: false,
+                    anchored: false,
+                    separator: None,
+                },
+            ),
+            DrawCommand::CloseWindow(1),
+        ];
+        for barrier in barriers {
+            let description = format!("{:?}", barrier);
+            let commands = vec![line(1, 0, "a"), barrier, line(1, 0, "b")];
+            assert_eq!(coalesce_draw_commands(commands).len(), 3, "{}", description);
+        }
+    }
+
+    #[test]
+    fn other_grids_are_not_affected() {
+        assert_coalesced(
+            vec![line(1, 0, "a"), line(2, 0, "b"), scroll(2), line(1, 0, "c")],
+            vec![line(2, 0, "b"), scroll(2), line(1, 0, "c")],
+        );
+        assert_coalesced(
+            vec![line(1, 0, "a"), scroll(2), line(1, 0, "b"), line(2, 0, "c")],
+            vec![scroll(2), line(1, 0, "b"), line(2, 0, "c")],
+        );
+    }
+
+    #[test]
+    fn only_the_last_cursor_update_is_kept() {
+        assert_coalesced(
+            vec![cursor(1), line(1, 0, "a"), cursor(2), scroll(1), cursor(3)],
+            vec![line(1, 0, "a"), scroll(1), cursor(3)],
+        );
+    }
 }
@@ -0,0 +1,271 @@
+use std::sync::Arc;
+
+use super::style::Style;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridCell {
+    pub text: String,
+    pub style: Option<Arc<Style>>,
+    pub double_width: bool,
+}
+
+impl GridCell {
+    pub fn empty() -> Self {
+        GridCell {
+            text: " ".to_owned(),
+            style: None,
+            double_width: false,
+        }
+    }
+}
+
+impl Default for GridCell {
+    fn default() -> Self {
+        GridCell::empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct CharacterGrid {
+    pub width: u64,
+    pub height: u64,
+    characters: Vec<GridCell>,
+}
+
+impl CharacterGrid {
+    pub fn new((width, height): (u64, u64)) -> Self {
+        CharacterGrid {
+            width,
+            height,
+            characters: vec![GridCell::empty(); (width * height) as usize],
+        }
+    }
+
+    fn cell_index(&self, x: u64, y: u64) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((x + y * self.width) as usize)
+        }
+    }
+
+    pub fn get_cell(&self, x: u64, y: u64) -> Option<&GridCell> {
+        self.cell_index(x, y).map(|index| &self.characters[index])
+    }
+
+    pub fn get_cell_mut(&mut self, x: u64, y: u64) -> Option<&mut GridCell> {
+        self.cell_index(x, y)
+            .map(move |index| &mut self.characters[index])
+    }
+
+    pub fn row(&self, y: u64) -> Option<&[GridCell]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = (y * self.width) as usize;
+        Some(&self.characters[start..start + self.width as usize])
+    }
+
+    /// Replaces a whole row, ignoring cells past the grid width
+    pub fn set_row(&mut self, y: u64, cells: Vec<GridCell>) {
+        for (x, cell) in cells.into_iter().enumerate() {
+            if let Some(grid_cell) = self.get_cell_mut(x as u64, y) {
+                *grid_cell = cell;
+            }
+        }
+    }
+
+    /// Marks every cell that is followed by the empty continuation cell of a wide character
+    pub fn update_double_width(&mut self, y: u64) {
+        for x in 0..self.width {
+            let double_width = self
+                .get_cell(x + 1, y)
+                .map(|next_cell| next_cell.text.is_empty())
+                .unwrap_or(false);
+            if let Some(cell) = self.get_cell_mut(x, y) {
+                cell.double_width = double_width;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.characters.iter_mut() {
+            *cell = GridCell::empty();
+        }
+    }
+
+    /// Resizes the grid keeping the content of the overlapping region
+    pub fn resize(&mut self, (width, height): (u64, u64)) {
+        let mut resized = CharacterGrid::new((width, height));
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                if let (Some(cell), Some(resized_cell)) =
+                    (self.get_cell(x, y), resized.get_cell_mut(x, y))
+                {
+                    *resized_cell = cell.clone();
+                }
+            }
+            resized.update_double_width(y);
+        }
+        *self = resized;
+    }
+
+    /// Moves the region `top..bottom` x `left..right` by `rows` and `columns` in place.
+    /// Positive values move the content up and left, matching neovim's grid_scroll.
+    pub fn scroll_region(
+        &mut self,
+        top: u64,
+        bottom: u64,
+        left: u64,
+        right: u64,
+        rows: i64,
+        columns: i64,
+    ) {
+        let (top, bottom, left, right) = (top as i64, bottom as i64, left as i64, right as i64);
+        let y_iter: Box<dyn Iterator<Item = i64>> = if rows > 0 {
+            Box::new((top + rows)..bottom)
+        } else {
+            Box::new((top..(bottom + rows)).rev())
+        };
+        for y in y_iter {
+            let dest_y = y - rows;
+            if dest_y < top || dest_y >= bottom {
+                continue;
+            }
+            let x_iter: Box<dyn Iterator<Item = i64>> = if columns > 0 {
+                Box::new((left + columns)..right)
+            } else {
+                Box::new((left..(right + columns)).rev())
+            };
+            for x in x_iter {
+                let dest_x = x - columns;
+                if dest_x < left || dest_x >= right {
+                    continue;
+                }
+                let cell = self.get_cell(x as u64, y as u64).cloned();
+                if let (Some(cell), Some(dest_cell)) =
+                    (cell, self.get_cell_mut(dest_x as u64, dest_y as u64))
+                {
+                    *dest_cell = cell;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_from(lines: &[&str]) -> CharacterGrid {
+        let width = lines.first().map_or(0, |line| line.chars().count()) as u64;
+        let mut grid = CharacterGrid::new((width, lines.len() as u64));
+        for (y, line) in lines.iter().enumerate() {
+            for (x, character) in line.chars().enumerate() {
+                if let Some(cell) = grid.get_cell_mut(x as u64, y as u64) {
+                    cell.text = character.to_string();
+                }
+            }
+        }
+        grid
+    }
+
+    fn row_cells(texts: &[&str]) -> Vec<GridCell> {
+        texts
+            .iter()
+            .map(|text| GridCell {
+                text: text.to_string(),
+                ..GridCell::empty()
+            })
+            .collect()
+    }
+
+    fn text(grid: &CharacterGrid) -> Vec<String> {
+        (0..grid.height)
+            .filter_map(|y| grid.row(y))
+            .map(|cells| cells.iter().map(|cell| cell.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn clear_empties_every_cell() {
+        let mut grid = grid_from(&["abc", "def"]);
+        grid.get_cell_mut(1, 1).unwrap().double_width = true;
+        grid.clear();
+        assert_eq!(text(&grid), vec!["   ", "   "]);
+        let row = grid.row(1).unwrap();
+        assert!(row.iter().all(|cell| *cell == GridCell::empty()));
+    }
+
+    #[test]
+    fn scroll_up_moves_a_partial_column_range() {
+        let mut grid = grid_from(&["abcd", "efgh", "ijkl", "mnop"]);
+        grid.scroll_region(0, 4, 1, 3, 1, 0);
+        // The last row of the region keeps its content until neovim redraws it
+        assert_eq!(text(&grid), vec!["afgd", "ejkh", "inol", "mnop"]);
+    }
+
+    #[test]
+    fn scroll_down_moves_a_partial_column_range() {
+        let mut grid = grid_from(&["abcd", "efgh", "ijkl", "mnop"]);
+        grid.scroll_region(1, 4, 0, 2, -2, 0);
+        assert_eq!(text(&grid), vec!["abcd", "efgh", "ijkl", "efop"]);
+    }
+
+    #[test]
+    fn scroll_moves_columns() {
+        let mut grid = grid_from(&["abcd", "efgh"]);
+        grid.scroll_region(0, 2, 0, 4, 0, 1);
+        assert_eq!(text(&grid), vec!["bcdd", "fghh"]);
+        grid.scroll_region(0, 1, 0, 4, 0, -2);
+        assert_eq!(text(&grid), vec!["bcbc", "fghh"]);
+    }
+
+    #[test]
+    fn resize_keeps_the_overlapping_content() {
+        let mut grid = grid_from(&["abc", "def", "ghi"]);
+        grid.resize((2, 4));
+        assert_eq!(text(&grid), vec!["ab", "de", "gh", "  "]);
+        grid.resize((3, 1));
+        assert_eq!(text(&grid), vec!["ab "]);
+    }
+
+    fn double_width_flags(grid: &CharacterGrid, y: u64) -> Vec<bool> {
+        grid.row(y)
+            .unwrap()
+            .iter()
+            .map(|cell| cell.double_width)
+            .collect()
+    }
+
+    #[test]
+    fn wide_characters_are_marked_double_width() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let mut grid = CharacterGrid::new((5, 3));
+        grid.set_row(0, row_cells(&["中", "", "文", "", "a"]));
+        grid.set_row(1, row_cells(&[family, "", "a", "b", "c"]));
+        grid.set_row(2, row_cells(&["न", "म", "स\u{94d}", "त\u{947}", " "]));
+        for y in 0..3 {
+            grid.update_double_width(y);
+        }
+        assert_eq!(
+            double_width_flags(&grid, 0),
+            vec![true, false, true, false, false]
+        );
+        assert_eq!(
+            double_width_flags(&grid, 1),
+            vec![true, false, false, false, false]
+        );
+        assert_eq!(double_width_flags(&grid, 2), vec![false; 5]);
+    }
+
+    #[test]
+    fn overwriting_a_continuation_cell_clears_double_width() {
+        let mut grid = CharacterGrid::new((3, 1));
+        grid.set_row(0, row_cells(&["中", "", "a"]));
+        grid.update_double_width(0);
+        grid.get_cell_mut(1, 0).unwrap().text = "b".to_owned();
+        grid.update_double_width(0);
+        assert_eq!(double_width_flags(&grid, 0), vec![false; 3]);
+    }
+}
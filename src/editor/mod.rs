@@ -1,33 +1,84 @@
 mod style;
+mod grid;
 mod window;
 mod draw_command_batcher;
 mod cursor;
+mod state_dump;
 
 
 pub use style::*;
+pub use grid::{CharacterGrid, GridCell};
 use window::*;
+pub use window::WindowDrawCommand;
 use draw_command_batcher::*;
 pub use cursor::*;
 
-use log::debug;
+use log::{debug, error, trace, warn};
 use std::collections::HashMap;
-use tokio::sync::mpsc::UnboundedReceiver;
+use std::sync::Arc;
+use tokio::sync::{mpsc::UnboundedReceiver, oneshot};
 
-use crate::{bridge::RedrawEvent, logging_sender::LoggingBoundedSender};
+use crate::{
+    bridge::{EditorMode, GridLineCell, GuiOption, RedrawEvent},
+    logging_sender::LoggingBoundedSender,
+};
+
+// Neovim's default zindex for floats, used when it does not send one
+const DEFAULT_SORT_ORDER: u64 = 50;
+// Floats anchored to each other in a cycle would otherwise recurse forever
+const MAX_ANCHOR_DEPTH: u32 = 16;
+// Neovim's zindex for the message grid, which covers floats with a lower zindex
+const MESSAGE_SORT_ORDER: u64 = 200;
 
 #[derive(Debug)]
 pub enum DrawCommand {
     CloseWindow(u64),
+    Window {
+        grid_id: u64,
+        command: WindowDrawCommand,
+    },
+    FontChanged(String),
+    LineSpaceChanged(u64),
+    DefaultStyleChanged(Style),
+    UpdateCursor(Cursor),
+    ModeChanged(EditorMode),
+}
+
+#[derive(Debug)]
+pub enum EditorCommand {
+    RedrawEvent(RedrawEvent),
+    /// Answers with the whole editor model as json
+    DumpState(oneshot::Sender<String>),
+    /// Answers with the grid under a screen cell and the cell position inside it
+    GridAt((f64, f64), oneshot::Sender<Option<(u64, (u64, u64))>>),
 }
 
 #[derive(Debug)]
 pub enum WindowCommand {
     TitleChanged(String),
     SetMouseEnable(bool),
+    SetBusy(bool),
+    /// Opens the OS window of an external grid, or resizes it if it is already open
+    ShowExternalWindow {
+        grid_id: u64,
+        window: u64,
+        width: u64,
+        height: u64,
+    },
+    CloseExternalWindow(u64),
 }
 
 struct Editor {
     pub windows: HashMap<u64, Window>,
+    pub defined_styles: HashMap<u64, Arc<Style>>,
+    pub default_style: Arc<Style>,
+    pub highlight_groups: HashMap<String, u64>,
+    pub cursor: Cursor,
+    pub mode_list: Vec<CursorMode>,
+    pub current_mode_index: Option<u64>,
+    pub current_mode: Option<EditorMode>,
+    pub options: HashMap<String, GuiOption>,
+    float_counter: u64,
     pub draw_command_batcher: DrawCommandBatcher,
     pub window_command_sender: LoggingBoundedSender<WindowCommand>,
 }
@@ -39,6 +90,15 @@ impl Editor {
     ) -> Self {
         Self {
             windows: HashMap::new(),
+            defined_styles: HashMap::new(),
+            default_style: Arc::new(Style::new(Colors::new(None, None, None))),
+            highlight_groups: HashMap::new(),
+            cursor: Cursor::new(),
+            mode_list: Vec::new(),
+            current_mode_index: None,
+            current_mode: None,
+            options: HashMap::new(),
+            float_counter: 0,
             draw_command_batcher: DrawCommandBatcher::new(batched_draw_command_sender),
             window_command_sender,
         }
@@ -51,23 +111,688 @@ impl Editor {
                     .send(WindowCommand::TitleChanged(title))
                     .ok();
             }
+            RedrawEvent::ModeInfoSet { cursor_modes } => {
+                self.mode_list = cursor_modes;
+                self.apply_current_mode();
+            }
+            RedrawEvent::OptionSet { gui_option } => self.set_option(gui_option),
+            RedrawEvent::ModeChange { mode, mode_index } => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::ModeChanged(mode.clone()))
+                    .ok();
+                self.current_mode = Some(mode);
+                self.current_mode_index = Some(mode_index);
+                self.apply_current_mode();
+            }
+            RedrawEvent::DefaultColorsSet { colors } => self.set_default_colors(colors),
+            RedrawEvent::HighlightAttributesDefine { id, style } => {
+                self.defined_styles.insert(id, Arc::new(style));
+                if self.current_mode().and_then(|mode| mode.style_id) == Some(id) {
+                    self.apply_current_mode();
+                }
+                if self.highlight_groups.get("MsgSeparator") == Some(&id) {
+                    self.update_message_separator();
+                }
+            }
+            RedrawEvent::HighlightGroupSet { name, id } => {
+                let is_message_separator = name == "MsgSeparator";
+                self.highlight_groups.insert(name, id);
+                if is_message_separator {
+                    self.update_message_separator();
+                }
+            }
+            RedrawEvent::Resize {
+                grid,
+                width,
+                height,
+            } => self.resize_window(grid, width, height),
+            RedrawEvent::GridLine {
+                grid,
+                row,
+                column_start,
+                cells,
+            } => self.draw_grid_line(grid, row, column_start, cells),
+            RedrawEvent::Clear { grid } => {
+                if let Some(window) = self.windows.get_mut(&grid) {
+                    let command = window.clear();
+                    self.send_window_command(grid, command);
+                }
+            }
+            RedrawEvent::Destroy { grid } => self.close_window(grid),
+            RedrawEvent::WindowPosition {
+                grid,
+                start_row,
+                start_column,
+                width,
+                height,
+            } => self.set_window_position(grid, start_column, start_row, width, height),
+            RedrawEvent::WindowFloatPosition {
+                grid,
+                anchor,
+                anchor_grid,
+                anchor_row,
+                anchor_column,
+                focusable,
+                sort_order,
+            } => {
+                let anchor_info = AnchorInfo {
+                    anchor_grid_id: anchor_grid,
+                    anchor_type: anchor,
+                    anchor_left: anchor_column,
+                    anchor_top: anchor_row,
+                    sort_order: sort_order.unwrap_or(DEFAULT_SORT_ORDER),
+                };
+                self.set_window_float_position(grid, anchor_info, focusable);
+            }
+            RedrawEvent::WindowExternalPosition { grid, window } => {
+                self.set_window_external_position(grid, window)
+            }
+            RedrawEvent::WindowHide { grid } | RedrawEvent::WindowClose { grid } => {
+                self.hide_window(grid)
+            }
+            RedrawEvent::MessageSetPosition {
+                grid,
+                row,
+                scrolled,
+                separator_character,
+            } => self.set_message_position(grid, row, scrolled, separator_character),
+            RedrawEvent::WindowViewport {
+                grid,
+                top_line,
+                scroll_delta,
+                ..
+            } => self.set_window_viewport(grid, top_line, scroll_delta),
+            RedrawEvent::CursorGoto { grid, row, column } => {
+                self.cursor.parent_window_id = grid;
+                self.cursor.grid_position = (column, row);
+            }
+            // Neovim expects the cursor to be hidden while it is busy
+            RedrawEvent::BusyStart => {
+                self.cursor.enabled = false;
+                self.send_os_window_command(WindowCommand::SetBusy(true));
+            }
+            RedrawEvent::BusyStop => {
+                self.cursor.enabled = true;
+                self.send_os_window_command(WindowCommand::SetBusy(false));
+            }
+            RedrawEvent::Flush => {
+                trace!("Image flushed");
+                self.send_cursor_info();
+                self.draw_command_batcher.send_batch().ok();
+            }
+            RedrawEvent::Scroll {
+                grid,
+                top,
+                bottom,
+                left,
+                right,
+                rows,
+                columns,
+            } => {
+                if let Some(window) = self.windows.get_mut(&grid) {
+                    let command = window.scroll_region(top, bottom, left, right, rows, columns);
+                    self.send_window_command(grid, command);
+                }
+            }
             _ => {
                 debug!("unhandled event {:?}", event);
             }
         }
     }
+
+    /// Neovim before 0.9 does not send the scroll delta, so it is derived from the top line.
+    /// Grid scrolls without a viewport change are redraws and should not animate.
+    fn set_window_viewport(&mut self, grid: u64, top_line: f64, scroll_delta: Option<f64>) {
+        let previous_top_line = match self.windows.get_mut(&grid) {
+            Some(window) => window.top_line.replace(top_line),
+            None => return,
+        };
+        let scroll_delta = scroll_delta.unwrap_or_else(|| {
+            previous_top_line.map_or(0.0, |previous_top_line| top_line - previous_top_line)
+        });
+        self.send_window_command(grid, WindowDrawCommand::Viewport { scroll_delta });
+    }
+
+    fn send_window_command(&self, grid_id: u64, command: WindowDrawCommand) {
+        self.draw_command_batcher
+            .queue(DrawCommand::Window { grid_id, command })
+            .ok();
+    }
+
+    /// Cells without explicit colors resolve against the defaults at draw time,
+    /// so every grid is redrawn once the defaults change
+    fn set_default_colors(&mut self, colors: Colors) {
+        let style = Style::new(colors);
+        self.default_style = Arc::new(style.clone());
+        self.draw_command_batcher
+            .queue(DrawCommand::DefaultStyleChanged(style))
+            .ok();
+        self.redraw_windows();
+    }
+
+    fn redraw_windows(&self) {
+        for (grid_id, window) in self.windows.iter() {
+            for command in window.redraw() {
+                self.send_window_command(*grid_id, command);
+            }
+        }
+    }
+
+    fn resize_window(&mut self, grid: u64, width: u64, height: u64) {
+        let command = match self.windows.get_mut(&grid) {
+            Some(window) => {
+                let command = window.resize(width, height);
+                if window.external {
+                    let window_command = external_window_command(window);
+                    self.send_os_window_command(window_command);
+                }
+                command
+            }
+            None => {
+                let window = Window::new(grid, WindowType::Editor, (0.0, 0.0), (width, height));
+                let command = window.position_command();
+                self.windows.insert(grid, window);
+                command
+            }
+        };
+        self.send_window_command(grid, command);
+        // Floats anchored to a corner of this grid, or clamped to the screen, may move
+        self.layout_floats(None);
+    }
+
+    fn set_window_position(
+        &mut self,
+        grid: u64,
+        start_column: u64,
+        start_row: u64,
+        width: u64,
+        height: u64,
+    ) {
+        let grid_position = (start_column as f64, start_row as f64);
+        let window = self.windows.entry(grid).or_insert_with(|| {
+            Window::new(grid, WindowType::Editor, grid_position, (width, height))
+        });
+        window.grid_position = grid_position;
+        window.anchor_info = None;
+        window.floating_order = None;
+        window.focusable = true;
+        window.hidden = false;
+        let was_external = std::mem::replace(&mut window.external, false);
+        let command = window.position_command();
+        self.send_window_command(grid, command);
+        if was_external {
+            self.send_os_window_command(WindowCommand::CloseExternalWindow(grid));
+        }
+        self.layout_floats(None);
+    }
+
+    fn set_window_float_position(&mut self, grid: u64, anchor_info: AnchorInfo, focusable: bool) {
+        let window = match self.windows.get_mut(&grid) {
+            Some(window) => window,
+            None => {
+                warn!("Float position received for unknown grid {}", grid);
+                return;
+            }
+        };
+        self.float_counter += 1;
+        window.floating_order = Some((anchor_info.sort_order, self.float_counter));
+        window.anchor_info = Some(anchor_info);
+        window.focusable = focusable;
+        window.hidden = false;
+        if std::mem::replace(&mut window.external, false) {
+            self.send_os_window_command(WindowCommand::CloseExternalWindow(grid));
+        }
+        self.layout_floats(Some(grid));
+    }
+
+    fn set_window_external_position(&mut self, grid: u64, window_handle: u64) {
+        let window = match self.windows.get_mut(&grid) {
+            Some(window) => window,
+            None => {
+                warn!("External position received for unknown grid {}", grid);
+                return;
+            }
+        };
+        window.anchor_info = None;
+        window.floating_order = None;
+        window.hidden = false;
+        window.external = true;
+        window.window_handle = Some(window_handle);
+        let command = window.position_command();
+        let window_command = external_window_command(window);
+        self.send_window_command(grid, command);
+        self.send_os_window_command(window_command);
+    }
+
+    fn hide_window(&mut self, grid: u64) {
+        if let Some(window) = self.windows.get_mut(&grid) {
+            window.hidden = true;
+            let was_external = std::mem::replace(&mut window.external, false);
+            self.send_window_command(grid, WindowDrawCommand::Hide);
+            if was_external {
+                self.send_os_window_command(WindowCommand::CloseExternalWindow(grid));
+            }
+        }
+    }
+
+    /// Places the message grid over the bottom of the screen, starting at `row`. Scrolled
+    /// messages grow over the buffer windows with a separator line above them, like in the TUI.
+    fn set_message_position(
+        &mut self,
+        grid: u64,
+        row: u64,
+        scrolled: bool,
+        separator_character: String,
+    ) {
+        let screen_size = self
+            .windows
+            .get(&1)
+            .map(|screen| (screen.get_width(), screen.get_height()));
+        let separator_style = self.message_separator_style();
+        let window = self.windows.entry(grid).or_insert_with(|| {
+            let (width, height) = screen_size.unwrap_or((1, 1));
+            let height = height.saturating_sub(row).max(1);
+            Window::new(
+                grid,
+                WindowType::Message,
+                (0.0, row as f64),
+                (width, height),
+            )
+        });
+        window.window_type = WindowType::Message;
+        window.grid_position = (0.0, row as f64);
+        window.anchor_info = None;
+        window.floating_order = Some((MESSAGE_SORT_ORDER, 1));
+        window.hidden = false;
+        window.separator = if scrolled && row > 0 {
+            Some(GridCell {
+                text: separator_character,
+                style: separator_style,
+                double_width: false,
+            })
+        } else {
+            None
+        };
+        let command = window.position_command();
+        self.send_window_command(grid, command);
+    }
+
+    fn message_separator_style(&self) -> Option<Arc<Style>> {
+        self.highlight_groups
+            .get("MsgSeparator")
+            .and_then(|id| self.defined_styles.get(id))
+            .cloned()
+    }
+
+    /// Restyles the separator above scrolled messages once the MsgSeparator highlight changes
+    fn update_message_separator(&mut self) {
+        let separator_style = self.message_separator_style();
+        let mut commands = Vec::new();
+        for (grid, window) in self.windows.iter_mut() {
+            if let Some(separator) = &mut window.separator {
+                separator.style = separator_style.clone();
+                commands.push((*grid, window.position_command()));
+            }
+        }
+        for (grid, command) in commands {
+            self.send_window_command(grid, command);
+        }
+    }
+
+    fn send_os_window_command(&self, window_command: WindowCommand) {
+        self.window_command_sender.send(window_command).ok();
+    }
+
+    /// Recomputes the screen position of every float and sends the ones which moved.
+    /// `changed_grid` is sent even if it did not move, because its order may have changed.
+    fn layout_floats(&mut self, changed_grid: Option<u64>) {
+        let positions: Vec<(u64, (f64, f64))> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| window.anchor_info.is_some())
+            .filter_map(|(grid, _)| Some((*grid, self.resolve_top_left(*grid, 0)?)))
+            .collect();
+        for (grid, grid_position) in positions {
+            if let Some(window) = self.windows.get_mut(&grid) {
+                if window.grid_position != grid_position || changed_grid == Some(grid) {
+                    window.grid_position = grid_position;
+                    let command = window.position_command();
+                    self.send_window_command(grid, command);
+                }
+            }
+        }
+    }
+
+    /// Resolves the absolute top left corner of a grid, following float anchors to
+    /// other floats and keeping floats on screen
+    fn resolve_top_left(&self, grid: u64, depth: u32) -> Option<(f64, f64)> {
+        let window = self.windows.get(&grid)?;
+        let anchor_info = match &window.anchor_info {
+            Some(anchor_info) => anchor_info,
+            None => return Some(window.grid_position),
+        };
+        if depth >= MAX_ANCHOR_DEPTH {
+            warn!("Float anchors of grid {} form a cycle", grid);
+            return None;
+        }
+        let (anchor_grid_left, anchor_grid_top) = self
+            .resolve_top_left(anchor_info.anchor_grid_id, depth + 1)
+            .unwrap_or((0.0, 0.0));
+        let (left, top) = anchor_info.top_left(window.get_width(), window.get_height());
+        Some(self.clamp_to_screen(
+            (anchor_grid_left + left, anchor_grid_top + top),
+            window.get_width(),
+            window.get_height(),
+        ))
+    }
+
+    fn clamp_to_screen(&self, (left, top): (f64, f64), width: u64, height: u64) -> (f64, f64) {
+        match self.windows.get(&1) {
+            Some(screen) => (
+                left.min(screen.get_width() as f64 - width as f64).max(0.0),
+                top.min(screen.get_height() as f64 - height as f64).max(0.0),
+            ),
+            None => (left.max(0.0), top.max(0.0)),
+        }
+    }
+
+    /// Finds the grid under a screen cell and the cell position inside it. Floats are
+    /// checked top most first, then the windows placed on grid 1 and grid 1 itself last.
+    /// Floats which are not focusable are never hit.
+    pub fn grid_at(&self, (column, row): (f64, f64)) -> Option<(u64, (u64, u64))> {
+        let mut windows: Vec<&Window> = self
+            .windows
+            .values()
+            .filter(|window| window.focusable && !window.hidden && !window.external)
+            .collect();
+        windows.sort_by_key(|window| (window.floating_order, window.grid_id() != 1));
+        windows
+            .into_iter()
+            .rev()
+            .find(|window| window.contains((column, row)))
+            .map(|window| {
+                let (left, top) = window.grid_position;
+                (
+                    window.grid_id(),
+                    ((column - left) as u64, (row - top) as u64),
+                )
+            })
+    }
+
+    fn draw_grid_line(&mut self, grid: u64, row: u64, column_start: u64, cells: Vec<GridLineCell>) {
+        if let Some(window) = self.windows.get_mut(&grid) {
+            if let Some(command) =
+                window.draw_grid_line(row, column_start, cells, &self.defined_styles)
+            {
+                self.send_window_command(grid, command);
+            }
+        }
+    }
+
+    fn close_window(&mut self, grid: u64) {
+        if let Some(window) = self.windows.remove(&grid) {
+            if window.external {
+                self.send_os_window_command(WindowCommand::CloseExternalWindow(grid));
+            }
+            self.draw_command_batcher
+                .queue(DrawCommand::CloseWindow(grid))
+                .ok();
+        }
+    }
+
+    fn current_mode(&self) -> Option<&CursorMode> {
+        self.current_mode_index
+            .and_then(|index| self.mode_list.get(index as usize))
+    }
+
+    fn apply_current_mode(&mut self) {
+        if let Some(mode) = self.current_mode().cloned() {
+            self.cursor.change_mode(&mode, &self.defined_styles);
+        }
+    }
+
+    /// Picks up the character under the cursor, which may have changed since it last moved
+    fn send_cursor_info(&mut self) {
+        let (column, row) = self.cursor.grid_position;
+        let cell = self
+            .windows
+            .get(&self.cursor.parent_window_id)
+            .and_then(|window| window.get_cell(column, row));
+        match cell {
+            // The empty right half of a wide character has nothing to draw under the cursor
+            Some(cell) if !cell.text.is_empty() => {
+                self.cursor.character = cell.text.clone();
+                self.cursor.double_width = cell.double_width;
+            }
+            _ => {
+                self.cursor.character = " ".to_owned();
+                self.cursor.double_width = false;
+            }
+        }
+        self.draw_command_batcher
+            .queue(DrawCommand::UpdateCursor(self.cursor.clone()))
+            .ok();
+    }
+
+    fn set_option(&mut self, gui_option: GuiOption) {
+        trace!("Option set {:?}", &gui_option);
+        self.options
+            .insert(gui_option.name().to_owned(), gui_option.clone());
+        match gui_option {
+            GuiOption::GuiFont(guifont) if guifont != "*" => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::FontChanged(guifont))
+                    .ok();
+            }
+            GuiOption::LineSpace(linespace) => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::LineSpaceChanged(linespace))
+                    .ok();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn external_window_command(window: &Window) -> WindowCommand {
+    WindowCommand::ShowExternalWindow {
+        grid_id: window.grid_id(),
+        window: window.window_handle.unwrap_or_default(),
+        width: window.get_width(),
+        height: window.get_height(),
+    }
 }
 
 pub fn start_editor(
-    mut redraw_event_receiver: UnboundedReceiver<RedrawEvent>,
+    mut editor_command_receiver: UnboundedReceiver<EditorCommand>,
     batched_draw_command_sender: LoggingBoundedSender<Vec<DrawCommand>>,
     window_command_sender: LoggingBoundedSender<WindowCommand>,
 ) {
     std::thread::spawn(move || {
         let mut editor = Editor::new(batched_draw_command_sender, window_command_sender);
-        while let Some(redraw_event) = redraw_event_receiver.blocking_recv() {
-            editor.handle_redraw_event(redraw_event);
+        while let Some(editor_command) = editor_command_receiver.blocking_recv() {
+            match editor_command {
+                EditorCommand::RedrawEvent(redraw_event) => {
+                    editor.handle_redraw_event(redraw_event);
+                }
+                EditorCommand::DumpState(state_sender) => match editor.dump_state() {
+                    Ok(state) => {
+                        state_sender.send(state).ok();
+                    }
+                    Err(error) => error!("Could not dump the editor state: {}", error),
+                },
+                EditorCommand::GridAt(position, grid_sender) => {
+                    grid_sender.send(editor.grid_at(position)).ok();
+                }
+            }
         }
     });
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::WindowAnchor::{self, NorthEast, NorthWest, SouthEast, SouthWest};
+    use std::sync::mpsc::channel;
+
+    fn test_editor() -> Editor {
+        let (batched_draw_command_sender, _) = channel();
+        let (window_command_sender, _) = channel();
+        let mut editor = Editor::new(
+            LoggingBoundedSender::attach(batched_draw_command_sender, "draw_command".to_owned()),
+            LoggingBoundedSender::attach(window_command_sender, "window_command".to_owned()),
+        );
+        editor.handle_redraw_event(RedrawEvent::Resize {
+            grid: 1,
+            width: 80,
+            height: 24,
+        });
+        editor
+    }
+
+    fn add_float(
+        editor: &mut Editor,
+        grid: u64,
+        (width, height): (u64, u64),
+        anchor: WindowAnchor,
+        anchor_grid: u64,
+        (anchor_column, anchor_row): (f64, f64),
+        focusable: bool,
+    ) {
+        editor.handle_redraw_event(RedrawEvent::Resize {
+            grid,
+            width,
+            height,
+        });
+        editor.handle_redraw_event(RedrawEvent::WindowFloatPosition {
+            grid,
+            anchor,
+            anchor_grid,
+            anchor_row,
+            anchor_column,
+            focusable,
+            sort_order: None,
+        });
+    }
+
+    fn position(editor: &Editor, grid: u64) -> (f64, f64) {
+        editor.windows[&grid].grid_position
+    }
+
+    #[test]
+    fn north_west_anchor_is_the_top_left_corner() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), NorthWest, 1, (3.0, 4.0), true);
+        assert_eq!(position(&editor, 2), (3.0, 4.0));
+    }
+
+    #[test]
+    fn north_east_anchor_is_the_top_right_corner() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), NorthEast, 1, (20.0, 4.0), true);
+        assert_eq!(position(&editor, 2), (10.0, 4.0));
+    }
+
+    #[test]
+    fn south_west_anchor_is_the_bottom_left_corner() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), SouthWest, 1, (3.0, 10.0), true);
+        assert_eq!(position(&editor, 2), (3.0, 5.0));
+    }
+
+    #[test]
+    fn south_east_anchor_is_the_bottom_right_corner() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), SouthEast, 1, (20.0, 10.0), true);
+        assert_eq!(position(&editor, 2), (10.0, 5.0));
+    }
+
+    #[test]
+    fn floats_anchored_to_floats_follow_them() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), NorthWest, 1, (5.0, 5.0), true);
+        add_float(&mut editor, 3, (4, 2), NorthWest, 2, (1.0, 2.0), true);
+        assert_eq!(position(&editor, 3), (6.0, 7.0));
+
+        editor.handle_redraw_event(RedrawEvent::WindowFloatPosition {
+            grid: 2,
+            anchor: WindowAnchor::NorthWest,
+            anchor_grid: 1,
+            anchor_row: 10.0,
+            anchor_column: 20.0,
+            focusable: true,
+            sort_order: None,
+        });
+        assert_eq!(position(&editor, 3), (21.0, 12.0));
+    }
+
+    #[test]
+    fn floats_are_clamped_to_the_screen() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), NorthWest, 1, (78.0, 22.0), true);
+        assert_eq!(position(&editor, 2), (70.0, 19.0));
+        add_float(&mut editor, 3, (10, 5), SouthEast, 1, (2.0, 1.0), true);
+        assert_eq!(position(&editor, 3), (0.0, 0.0));
+    }
+
+    #[test]
+    fn hit_testing_skips_floats_which_are_not_focusable() {
+        let mut editor = test_editor();
+        add_float(&mut editor, 2, (10, 5), NorthWest, 1, (5.0, 5.0), true);
+        add_float(&mut editor, 3, (10, 5), NorthWest, 1, (8.0, 6.0), false);
+        assert_eq!(editor.grid_at((9.0, 7.0)), Some((2, (4, 2))));
+        assert_eq!(editor.grid_at((16.0, 7.0)), Some((1, (16, 7))));
+    }
+
+    #[test]
+    fn hit_testing_prefers_windows_over_grid_1() {
+        let mut editor = test_editor();
+        // Enough windows that hash map order can not put grid 1 last by chance
+        for grid in 2..12 {
+            editor.handle_redraw_event(RedrawEvent::WindowPosition {
+                grid,
+                start_row: grid,
+                start_column: 0,
+                width: 80,
+                height: 1,
+            });
+        }
+        for grid in 2..12 {
+            assert_eq!(editor.grid_at((5.0, grid as f64)), Some((grid, (5, 0))));
+        }
+        assert_eq!(editor.grid_at((5.0, 20.0)), Some((1, (5, 20))));
+    }
+
+    #[test]
+    fn scrolled_messages_show_a_separator_above_the_message_grid() {
+        let mut editor = test_editor();
+        let separator_style = Style::new(Colors::new(None, None, None));
+        editor.handle_redraw_event(RedrawEvent::HighlightAttributesDefine {
+            id: 7,
+            style: separator_style.clone(),
+        });
+        editor.handle_redraw_event(RedrawEvent::HighlightGroupSet {
+            name: "MsgSeparator".to_owned(),
+            id: 7,
+        });
+        editor.handle_redraw_event(RedrawEvent::MessageSetPosition {
+            grid: 3,
+            row: 20,
+            scrolled: true,
+            separator_character: "-".to_owned(),
+        });
+        let separator = editor.windows[&3].separator.clone().unwrap();
+        assert_eq!(separator.text, "-");
+        assert_eq!(separator.style.as_deref(), Some(&separator_style));
+        assert_eq!(position(&editor, 3), (0.0, 20.0));
+        assert_eq!(editor.windows.len(), 2);
+
+        editor.handle_redraw_event(RedrawEvent::MessageSetPosition {
+            grid: 3,
+            row: 23,
+            scrolled: false,
+            separator_character: "-".to_owned(),
+        });
+        assert!(editor.windows[&3].separator.is_none());
+    }
+}
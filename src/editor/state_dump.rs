@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{AnchorInfo, Cursor, CursorMode, Editor, Style, WindowType};
+use crate::bridge::{EditorMode, GuiOption};
+
+#[derive(Serialize)]
+struct GridState<'a> {
+    grid_id: u64,
+    window_type: WindowType,
+    grid_position: (f64, f64),
+    width: u64,
+    height: u64,
+    anchor_info: Option<&'a AnchorInfo>,
+    floating_order: Option<(u64, u64)>,
+    focusable: bool,
+    external: bool,
+    window_handle: Option<u64>,
+    hidden: bool,
+    separator: Option<&'a str>,
+    lines: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EditorState<'a> {
+    grids: Vec<GridState<'a>>,
+    default_style: &'a Style,
+    highlights: BTreeMap<u64, &'a Style>,
+    highlight_groups: BTreeMap<&'a str, u64>,
+    cursor: &'a Cursor,
+    mode_list: &'a [CursorMode],
+    current_mode: Option<&'a EditorMode>,
+    current_mode_index: Option<u64>,
+    options: BTreeMap<&'a str, &'a GuiOption>,
+}
+
+impl Editor {
+    /// Serializes the model with sorted keys, so dumps of the same screen diff cleanly
+    pub fn dump_state(&self) -> serde_json::Result<String> {
+        let mut grids: Vec<GridState> = self
+            .windows
+            .values()
+            .map(|window| GridState {
+                grid_id: window.grid_id(),
+                window_type: window.window_type,
+                grid_position: window.grid_position,
+                width: window.get_width(),
+                height: window.get_height(),
+                anchor_info: window.anchor_info.as_ref(),
+                floating_order: window.floating_order,
+                focusable: window.focusable,
+                external: window.external,
+                window_handle: window.window_handle,
+                hidden: window.hidden,
+                separator: window
+                    .separator
+                    .as_ref()
+                    .map(|separator| separator.text.as_str()),
+                lines: window.text_lines(),
+            })
+            .collect();
+        grids.sort_by_key(|grid| grid.grid_id);
+
+        let state = EditorState {
+            grids,
+            default_style: &self.default_style,
+            highlights: self
+                .defined_styles
+                .iter()
+                .map(|(id, style)| (*id, style.as_ref()))
+                .collect(),
+            highlight_groups: self
+                .highlight_groups
+                .iter()
+                .map(|(name, id)| (name.as_str(), *id))
+                .collect(),
+            cursor: &self.cursor,
+            mode_list: &self.mode_list,
+            current_mode: self.current_mode.as_ref(),
+            current_mode_index: self.current_mode_index,
+            options: self
+                .options
+                .iter()
+                .map(|(name, option)| (name.as_str(), option))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&state)
+    }
+}
@@ -1,13 +1,58 @@
-use skia_safe::Color4f;
+use serde::{Serialize, Serializer};
+use skia_safe::{colors, Color4f};
 
-#[derive(new, Debug, PartialEq, Clone)]
+const FALLBACK_FOREGROUND: Color4f = colors::WHITE;
+const FALLBACK_BACKGROUND: Color4f = colors::BLACK;
+
+/// Colors are written as `#rrggbb`, the way neovim reports highlight colors
+fn serialize_color<S: Serializer>(
+    color: &Option<Color4f>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match color {
+        Some(color) => {
+            let color = color.to_color();
+            let hex = format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b());
+            serializer.serialize_str(&hex)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(new, Debug, PartialEq, Clone, Serialize)]
 pub struct Colors {
+    #[serde(serialize_with = "serialize_color")]
     pub foreground: Option<Color4f>,
+    #[serde(serialize_with = "serialize_color")]
     pub background: Option<Color4f>,
+    #[serde(serialize_with = "serialize_color")]
     pub special: Option<Color4f>,
 }
 
-#[derive(new, Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum UnderlineStyle {
+    Underline,
+    UnderDouble,
+    UnderCurl,
+    UnderDotted,
+    UnderDashed,
+}
+
+impl UnderlineStyle {
+    /// Maps hl_attr_define attributes, including the names used before neovim 0.8
+    pub fn from_attribute_name(name: &str) -> Option<UnderlineStyle> {
+        match name {
+            "underline" => Some(UnderlineStyle::Underline),
+            "underdouble" | "underlineline" => Some(UnderlineStyle::UnderDouble),
+            "undercurl" => Some(UnderlineStyle::UnderCurl),
+            "underdotted" | "underdot" => Some(UnderlineStyle::UnderDotted),
+            "underdashed" | "underdash" => Some(UnderlineStyle::UnderDashed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(new, Debug, Clone, PartialEq, Serialize)]
 pub struct Style {
     pub colors: Colors,
     #[new(default)]
@@ -19,39 +64,168 @@ pub struct Style {
     #[new(default)]
     pub strikethrough: bool,
     #[new(default)]
-    pub underline: bool,
-    #[new(default)]
-    pub undercurl: bool,
+    pub underline: Option<UnderlineStyle>,
     #[new(default)]
     pub blend: u8,
 }
 
 impl Style {
+    fn resolved_foreground(&self, default_colors: &Colors) -> Color4f {
+        self.colors
+            .foreground
+            .or(default_colors.foreground)
+            .unwrap_or(FALLBACK_FOREGROUND)
+    }
+
+    fn resolved_background(&self, default_colors: &Colors) -> Color4f {
+        self.colors
+            .background
+            .or(default_colors.background)
+            .unwrap_or(FALLBACK_BACKGROUND)
+    }
+
     pub fn foreground(&self, default_colors: &Colors) -> Color4f {
         if self.reverse {
-            self.colors
-                .background
-                .unwrap_or(default_colors.background.unwrap())
+            self.resolved_background(default_colors)
         } else {
-            self.colors
-                .background
-                .unwrap_or(default_colors.foreground.unwrap())
+            self.resolved_foreground(default_colors)
         }
     }
 
+    /// The background color with `blend` applied as transparency, 0 being opaque and 100 invisible
     pub fn background(&self, default_colors: &Colors) -> Color4f {
-        if self.reverse {
-            self.colors
-                .foreground
-                .unwrap_or(default_colors.background.unwrap())
+        let color = if self.reverse {
+            self.resolved_foreground(default_colors)
         } else {
-            self.colors
-                .background
-                .unwrap_or(default_colors.foreground.unwrap())
+            self.resolved_background(default_colors)
+        };
+        Color4f {
+            a: 1.0 - self.blend.min(100) as f32 / 100.0,
+            ..color
         }
     }
 
+    /// Whether the background comes from the default colors rather than this highlight
+    pub fn has_default_background(&self) -> bool {
+        !self.reverse && self.colors.background.is_none()
+    }
+
+    /// The underline color, falling back to the foreground this highlight sets and then to
+    /// the default special color
     pub fn special(&self, default_colors: &Colors) -> Color4f {
-        self.colors.special.unwrap_or(default_colors.special.unwrap())
+        let foreground = if self.reverse {
+            self.colors.background
+        } else {
+            self.colors.foreground
+        };
+        self.colors
+            .special
+            .or(foreground)
+            .or(default_colors.special)
+            .unwrap_or_else(|| self.foreground(default_colors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FG: Color4f = colors::RED;
+    const BG: Color4f = colors::GREEN;
+    const SP: Color4f = colors::CYAN;
+    const DEFAULT_FG: Color4f = colors::YELLOW;
+    const DEFAULT_BG: Color4f = colors::BLUE;
+    const DEFAULT_SP: Color4f = colors::MAGENTA;
+
+    fn default_colors() -> Colors {
+        Colors::new(Some(DEFAULT_FG), Some(DEFAULT_BG), Some(DEFAULT_SP))
+    }
+
+    #[test]
+    fn foreground_and_background_resolve_every_reverse_combination() {
+        let cases = [
+            (false, None, None, DEFAULT_FG, DEFAULT_BG),
+            (false, Some(FG), None, FG, DEFAULT_BG),
+            (false, None, Some(BG), DEFAULT_FG, BG),
+            (false, Some(FG), Some(BG), FG, BG),
+            (true, None, None, DEFAULT_BG, DEFAULT_FG),
+            (true, Some(FG), None, DEFAULT_BG, FG),
+            (true, None, Some(BG), BG, DEFAULT_FG),
+            (true, Some(FG), Some(BG), BG, FG),
+        ];
+        for (reverse, foreground, background, expected_foreground, expected_background) in
+            cases.iter().copied()
+        {
+            let mut style = Style::new(Colors::new(foreground, background, None));
+            style.reverse = reverse;
+            let description = format!("{:?}", style);
+            assert_eq!(
+                style.foreground(&default_colors()),
+                expected_foreground,
+                "{}",
+                description
+            );
+            assert_eq!(
+                style.background(&default_colors()),
+                expected_background,
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn missing_default_colors_fall_back_to_white_on_black() {
+        let default_colors = Colors::new(None, None, None);
+        let mut style = Style::new(Colors::new(None, None, None));
+        assert_eq!(style.foreground(&default_colors), FALLBACK_FOREGROUND);
+        assert_eq!(style.background(&default_colors), FALLBACK_BACKGROUND);
+        assert_eq!(style.special(&default_colors), FALLBACK_FOREGROUND);
+        style.reverse = true;
+        assert_eq!(style.foreground(&default_colors), FALLBACK_BACKGROUND);
+        assert_eq!(style.background(&default_colors), FALLBACK_FOREGROUND);
+    }
+
+    #[test]
+    fn blend_makes_the_background_transparent() {
+        let cases = [(0, 1.0), (50, 0.5), (100, 0.0), (200, 0.0)];
+        for (blend, expected_alpha) in cases.iter().copied() {
+            let mut style = Style::new(Colors::new(Some(FG), Some(BG), None));
+            style.blend = blend;
+            let background = style.background(&default_colors());
+            assert_eq!(
+                background,
+                Color4f {
+                    a: expected_alpha,
+                    ..BG
+                },
+                "blend {}",
+                blend
+            );
+            assert_eq!(style.foreground(&default_colors()), FG, "blend {}", blend);
+        }
+    }
+
+    #[test]
+    fn special_falls_back_to_the_foreground_and_then_the_default() {
+        let cases = [
+            (Some(SP), Some(FG), false, SP),
+            (None, Some(FG), false, FG),
+            (None, None, false, DEFAULT_SP),
+            (None, Some(FG), true, DEFAULT_SP),
+        ];
+        for (special, foreground, reverse, expected_special) in cases.iter().copied() {
+            let mut style = Style::new(Colors::new(foreground, None, special));
+            style.reverse = reverse;
+            assert_eq!(
+                style.special(&default_colors()),
+                expected_special,
+                "{:?}",
+                style
+            );
+        }
+        let default_colors = Colors::new(Some(DEFAULT_FG), None, None);
+        let style = Style::new(Colors::new(None, None, None));
+        assert_eq!(style.special(&default_colors), DEFAULT_FG);
     }
 }
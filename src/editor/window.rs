@@ -1,12 +1,302 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::grid::{CharacterGrid, GridCell};
+use super::style::Style;
+use crate::bridge::{GridLineCell, WindowAnchor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum WindowType {
-	Editor,
-	Message,
+    Editor,
+    Message,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnchorInfo {
+    pub anchor_grid_id: u64,
+    pub anchor_type: WindowAnchor,
+    pub anchor_left: f64,
+    pub anchor_top: f64,
+    pub sort_order: u64,
+}
+
+impl AnchorInfo {
+    /// The top left corner of a `width` x `height` float relative to its anchor grid
+    pub fn top_left(&self, width: u64, height: u64) -> (f64, f64) {
+        let (width, height) = (width as f64, height as f64);
+        match self.anchor_type {
+            WindowAnchor::NorthWest => (self.anchor_left, self.anchor_top),
+            WindowAnchor::NorthEast => (self.anchor_left - width, self.anchor_top),
+            WindowAnchor::SouthWest => (self.anchor_left, self.anchor_top - height),
+            WindowAnchor::SouthEast => (self.anchor_left - width, self.anchor_top - height),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WindowDrawCommand {
+    /// `floating_order` is None for regular windows. Floats are drawn above them,
+    /// lowest order first. External windows are drawn in their own OS window instead.
+    /// `anchored` marks floats placed by win_float_pos, as opposed to message grids.
+    /// Scrolled message grids draw `separator` across the row above them.
+    Position {
+        grid_position: (f64, f64),
+        width: u64,
+        height: u64,
+        floating_order: Option<(u64, u64)>,
+        external: bool,
+        anchored: bool,
+        separator: Option<GridCell>,
+    },
+    Line {
+        row: u64,
+        cells: Vec<GridCell>,
+    },
+    Scroll {
+        top: u64,
+        bottom: u64,
+        left: u64,
+        right: u64,
+        rows: i64,
+        columns: i64,
+    },
+    Clear,
+    Hide,
+    /// Sent for every win_viewport, `scroll_delta` being the lines the view moved down by
+    Viewport {
+        scroll_delta: f64,
+    },
 }
 
 pub struct Window {
-	grid_id: u64,
+    grid_id: u64,
+    grid: CharacterGrid,
+    pub window_type: WindowType,
+    pub grid_position: (f64, f64),
+    pub anchor_info: Option<AnchorInfo>,
+    pub floating_order: Option<(u64, u64)>,
+    pub focusable: bool,
+    pub external: bool,
+    pub window_handle: Option<u64>,
+    pub hidden: bool,
+    /// The first buffer line shown, as of the last win_viewport
+    pub top_line: Option<f64>,
+    /// The MsgSeparator cell shown above message grids scrolled over the buffers
+    pub separator: Option<GridCell>,
 }
 
 impl Window {
+    pub fn new(
+        grid_id: u64,
+        window_type: WindowType,
+        grid_position: (f64, f64),
+        grid_size: (u64, u64),
+    ) -> Self {
+        Window {
+            grid_id,
+            grid: CharacterGrid::new(grid_size),
+            window_type,
+            grid_position,
+            anchor_info: None,
+            floating_order: None,
+            focusable: true,
+            external: false,
+            window_handle: None,
+            hidden: false,
+            top_line: None,
+            separator: None,
+        }
+    }
+
+    pub fn grid_id(&self) -> u64 {
+        self.grid_id
+    }
+
+    pub fn get_width(&self) -> u64 {
+        self.grid.width
+    }
+
+    pub fn get_height(&self) -> u64 {
+        self.grid.height
+    }
+
+    pub fn get_cell(&self, x: u64, y: u64) -> Option<&GridCell> {
+        self.grid.get_cell(x, y)
+    }
+
+    pub fn position_command(&self) -> WindowDrawCommand {
+        WindowDrawCommand::Position {
+            grid_position: self.grid_position,
+            width: self.grid.width,
+            height: self.grid.height,
+            floating_order: self.floating_order,
+            external: self.external,
+            anchored: self.anchor_info.is_some(),
+            separator: self.separator.clone(),
+        }
+    }
+
+    pub fn contains(&self, (column, row): (f64, f64)) -> bool {
+        let (left, top) = self.grid_position;
+        column >= left
+            && row >= top
+            && column < left + self.grid.width as f64
+            && row < top + self.grid.height as f64
+    }
 
-}
\ No newline at end of file
+    fn line_command(&self, row: u64) -> Option<WindowDrawCommand> {
+        self.grid.row(row).map(|cells| WindowDrawCommand::Line {
+            row,
+            cells: cells.to_vec(),
+        })
+    }
+
+    /// Applies a grid_line event. Cells without a highlight id reuse the style of the
+    /// previous cell in the same event, and `repeat` writes a cell several times.
+    pub fn draw_grid_line(
+        &mut self,
+        row: u64,
+        column_start: u64,
+        cells: Vec<GridLineCell>,
+        defined_styles: &HashMap<u64, Arc<Style>>,
+    ) -> Option<WindowDrawCommand> {
+        if row >= self.grid.height {
+            return None;
+        }
+        let mut column = column_start;
+        let mut previous_style = None;
+        for cell in cells {
+            let style = match cell.highlight_id {
+                Some(0) => None,
+                Some(highlight_id) => defined_styles.get(&highlight_id).cloned(),
+                None => previous_style,
+            };
+            for _ in 0..cell.repeat.unwrap_or(1) {
+                if let Some(grid_cell) = self.grid.get_cell_mut(column, row) {
+                    *grid_cell = GridCell {
+                        text: cell.text.clone(),
+                        style: style.clone(),
+                        double_width: false,
+                    };
+                }
+                column += 1;
+            }
+            previous_style = style;
+        }
+        self.grid.update_double_width(row);
+        self.line_command(row)
+    }
+
+    pub fn scroll_region(
+        &mut self,
+        top: u64,
+        bottom: u64,
+        left: u64,
+        right: u64,
+        rows: i64,
+        columns: i64,
+    ) -> WindowDrawCommand {
+        self.grid
+            .scroll_region(top, bottom, left, right, rows, columns);
+        WindowDrawCommand::Scroll {
+            top,
+            bottom,
+            left,
+            right,
+            rows,
+            columns,
+        }
+    }
+
+    pub fn clear(&mut self) -> WindowDrawCommand {
+        self.grid.clear();
+        WindowDrawCommand::Clear
+    }
+
+    pub fn resize(&mut self, width: u64, height: u64) -> WindowDrawCommand {
+        self.grid.resize((width, height));
+        self.position_command()
+    }
+
+    /// The text of every row, with the empty continuation cells of wide characters
+    pub fn text_lines(&self) -> Vec<String> {
+        (0..self.grid.height)
+            .filter_map(|row| self.grid.row(row))
+            .map(|cells| cells.iter().map(|cell| cell.text.as_str()).collect())
+            .collect()
+    }
+
+    /// Describes the whole window so the renderer can rebuild it from scratch
+    pub fn redraw(&self) -> Vec<WindowDrawCommand> {
+        let mut commands = vec![self.position_command(), WindowDrawCommand::Clear];
+        commands.extend((0..self.grid.height).filter_map(|row| self.line_command(row)));
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Colors;
+
+    fn cell(text: &str, highlight_id: Option<u64>, repeat: Option<u64>) -> GridLineCell {
+        GridLineCell {
+            text: text.to_owned(),
+            highlight_id,
+            repeat,
+        }
+    }
+
+    fn defined_styles() -> HashMap<u64, Arc<Style>> {
+        (1..=2)
+            .map(|id| {
+                let colors = Colors::new(None, None, None);
+                let mut style = Style::new(colors);
+                style.bold = id == 1;
+                (id, Arc::new(style))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grid_line_repeats_cells() {
+        let mut window = Window::new(1, WindowType::Editor, (0.0, 0.0), (8, 1));
+        let cells = vec![
+            cell("a", Some(0), Some(3)),
+            cell("b", None, None),
+            cell("c", None, Some(2)),
+        ];
+        window.draw_grid_line(0, 1, cells, &HashMap::new());
+        assert_eq!(window.text_lines(), vec![" aaabcc "]);
+    }
+
+    #[test]
+    fn grid_line_carries_the_highlight_over_to_following_cells() {
+        let defined_styles = defined_styles();
+        let mut window = Window::new(1, WindowType::Editor, (0.0, 0.0), (5, 1));
+        let cells = vec![
+            cell("a", Some(1), None),
+            cell("b", None, Some(2)),
+            cell("c", Some(2), None),
+            cell("d", Some(0), None),
+        ];
+        window.draw_grid_line(0, 0, cells, &defined_styles);
+        let style = |column| window.get_cell(column, 0).unwrap().style.clone();
+        assert_eq!(style(0), defined_styles.get(&1).cloned());
+        assert_eq!(style(1), defined_styles.get(&1).cloned());
+        assert_eq!(style(2), defined_styles.get(&1).cloned());
+        assert_eq!(style(3), defined_styles.get(&2).cloned());
+        assert_eq!(style(4), None);
+    }
+
+    #[test]
+    fn grid_line_ignores_cells_outside_the_grid() {
+        let mut window = Window::new(1, WindowType::Editor, (0.0, 0.0), (3, 1));
+        assert!(window
+            .draw_grid_line(1, 0, vec![cell("a", None, None)], &HashMap::new())
+            .is_none());
+        window.draw_grid_line(0, 1, vec![cell("b", None, Some(5))], &HashMap::new());
+        assert_eq!(window.text_lines(), vec![" bb"]);
+    }
+}
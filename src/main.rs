@@ -17,7 +17,7 @@ extern crate derive_new;
 
 use log::trace;
 use std::sync::{atomic::AtomicBool, mpsc::channel, Arc};
-use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::{mpsc::unbounded_channel, oneshot};
 
 use crate::cmd_line::*;
 use crate::editor::start_editor;
@@ -46,12 +46,15 @@ fn main() {
     windows_fix_dpi();
 
     window::WindowSettings::register();
+    window::KeyboardSettings::register();
+    render::CursorSettings::register();
+    render::RenderSettings::register();
 
     let running = Arc::new(AtomicBool::new(true));
 
-    let (redraw_event_sender, redraw_event_receiver) = unbounded_channel();
-    let logging_redraw_event_sender =
-        LoggingUnboundedSender::attach(redraw_event_sender, "redraw_event".to_owned());
+    let (editor_command_sender, editor_command_receiver) = unbounded_channel();
+    let logging_editor_command_sender =
+        LoggingUnboundedSender::attach(editor_command_sender, "editor_command".to_owned());
     let (batched_draw_command_sender, batched_draw_command_receiver) = channel();
     let logging_batched_draw_command_sender = LoggingBoundedSender::attach(
         batched_draw_command_sender,
@@ -65,19 +68,23 @@ fn main() {
     let logging_window_command_sender =
         LoggingBoundedSender::attach(window_command_sender, "window_command".to_owned());
 
+    let (grid_size_sender, grid_size_receiver) = oneshot::channel();
+
     let _bridge = start_bridge(
         logging_ui_command_sender.clone(),
         ui_command_receiver,
-        logging_redraw_event_sender,
+        logging_editor_command_sender,
+        grid_size_receiver,
         running.clone(),
     );
 
-    start_editor(redraw_event_receiver, logging_batched_draw_command_sender, logging_window_command_sender);
+    start_editor(editor_command_receiver, logging_batched_draw_command_sender, logging_window_command_sender);
 
     create_window(
         batched_draw_command_receiver,
         window_command_receiver,
         logging_ui_command_sender,
+        grid_size_sender,
         running,
     );
 }
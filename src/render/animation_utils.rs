@@ -0,0 +1,35 @@
+/// Easing curves map the linear progress `t` of an animation in `0..=1` to how far along the
+/// animated value is. The ease out curves start fast and settle gently on the destination.
+pub fn ease_linear(t: f32) -> f32 {
+    t
+}
+
+pub fn ease_out_quad(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(2)
+}
+
+pub fn ease_out_cubic(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+pub fn ease_out_expo(t: f32) -> f32 {
+    if t >= 1.0 {
+        1.0
+    } else {
+        1.0 - 2.0_f32.powf(-10.0 * t)
+    }
+}
+
+/// Looks up an easing curve by the name used in settings, falling back to `expo`
+pub fn easing_function(name: &str) -> fn(f32) -> f32 {
+    match name {
+        "linear" => ease_linear,
+        "quad" => ease_out_quad,
+        "cubic" => ease_out_cubic,
+        _ => ease_out_expo,
+    }
+}
+
+pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
+    start + (end - start) * t
+}
@@ -0,0 +1,495 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use skia_safe::{BlendMode, Canvas, Paint, PaintStyle, Path, Point, Rect};
+
+use crate::bridge::EditorMode;
+use crate::editor::{Cursor, CursorShape, Style};
+use crate::settings::SETTINGS;
+
+use super::animation_utils::{easing_function, lerp};
+use super::cursor_vfx::{CursorVfx, VfxMode};
+use super::grid_render::GridRender;
+use super::settings::CursorSettings;
+
+// Bars without a cell percentage from the mode info get neovim's default size
+const DEFAULT_BAR_PERCENTAGE: f32 = 0.25;
+
+// Typing moves the cursor a cell or two at a time, which should not leave a trail
+const SHORT_JUMP_CELLS: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlinkState {
+    Waiting,
+    On,
+    Off,
+}
+
+/// Follows neovim's blink cycle: the cursor stays visible for `blinkwait` after it changes,
+/// then alternates between `blinkoff` and `blinkon`. A missing or zero timing disables it.
+struct BlinkStatus {
+    state: BlinkState,
+    last_transition: Instant,
+}
+
+impl BlinkStatus {
+    fn new() -> Self {
+        BlinkStatus {
+            state: BlinkState::Waiting,
+            last_transition: Instant::now(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = BlinkState::Waiting;
+        self.last_transition = Instant::now();
+    }
+
+    fn timings(cursor: &Cursor) -> Option<(u64, u64, u64)> {
+        match (cursor.blinkwait, cursor.blinkon, cursor.blinkoff) {
+            (Some(wait), Some(on), Some(off)) if wait > 0 && on > 0 && off > 0 => {
+                Some((wait, on, off))
+            }
+            _ => None,
+        }
+    }
+
+    fn state_duration(&self, cursor: &Cursor) -> Option<Duration> {
+        Self::timings(cursor).map(|(wait, on, off)| {
+            Duration::from_millis(match self.state {
+                BlinkState::Waiting => wait,
+                BlinkState::On => on,
+                BlinkState::Off => off,
+            })
+        })
+    }
+
+    /// Advances past every transition due by `now` and returns whether the cursor is visible
+    fn update(&mut self, cursor: &Cursor, now: Instant) -> bool {
+        while let Some(state_duration) = self.state_duration(cursor) {
+            let transition = self.last_transition + state_duration;
+            if transition > now {
+                break;
+            }
+            self.state = match self.state {
+                BlinkState::Waiting | BlinkState::On => BlinkState::Off,
+                BlinkState::Off => BlinkState::On,
+            };
+            self.last_transition = transition;
+        }
+        if self.state_duration(cursor).is_none() {
+            self.state = BlinkState::Waiting;
+        }
+        self.state != BlinkState::Off
+    }
+
+    fn next_transition(&self, cursor: &Cursor) -> Option<Instant> {
+        self.state_duration(cursor)
+            .map(|state_duration| self.last_transition + state_duration)
+    }
+}
+
+/// One corner of the cursor shape, animated on its own so the shape can stretch
+#[derive(Clone, Copy)]
+struct Corner {
+    start_position: Point,
+    current_position: Point,
+    destination: Point,
+    t: f32,
+    length_multiplier: f32,
+}
+
+impl Corner {
+    fn new() -> Self {
+        Corner {
+            start_position: Point::default(),
+            current_position: Point::default(),
+            destination: Point::default(),
+            t: 1.0,
+            length_multiplier: 1.0,
+        }
+    }
+
+    fn jump_to(&mut self, destination: Point) {
+        self.start_position = destination;
+        self.current_position = destination;
+        self.destination = destination;
+        self.t = 1.0;
+    }
+
+    fn animate_to(&mut self, destination: Point, length_multiplier: f32) {
+        if destination != self.destination {
+            self.start_position = self.current_position;
+            self.destination = destination;
+            self.t = 0.0;
+            self.length_multiplier = length_multiplier;
+        }
+    }
+
+    /// Advances the animation by `dt` seconds and returns whether it is still running
+    fn update(&mut self, settings: &CursorSettings, dt: f32) -> bool {
+        let length = settings.animation_length * self.length_multiplier;
+        self.t = if length > 0.0 {
+            (self.t + dt / length).min(1.0)
+        } else {
+            1.0
+        };
+        let ease = easing_function(&settings.animation_easing);
+        let progress = ease(self.t);
+        self.current_position = Point::new(
+            lerp(self.start_position.x, self.destination.x, progress),
+            lerp(self.start_position.y, self.destination.y, progress),
+        );
+        self.t < 1.0
+    }
+}
+
+/// The corners of a rectangle, clockwise from the top left
+fn rect_corners(rect: Rect) -> [Point; 4] {
+    [
+        Point::new(rect.left, rect.top),
+        Point::new(rect.right, rect.top),
+        Point::new(rect.right, rect.bottom),
+        Point::new(rect.left, rect.bottom),
+    ]
+}
+
+pub struct CursorRenderer {
+    pub cursor: Cursor,
+    pub focused: bool,
+    blink_status: BlinkStatus,
+    visible: bool,
+    pub editor_mode: Option<EditorMode>,
+    corners: [Corner; 4],
+    destination: Option<Rect>,
+    vfx: Option<CursorVfx>,
+}
+
+impl CursorRenderer {
+    pub fn new() -> Self {
+        CursorRenderer {
+            cursor: Cursor::new(),
+            focused: true,
+            blink_status: BlinkStatus::new(),
+            visible: true,
+            editor_mode: None,
+            corners: [Corner::new(); 4],
+            destination: None,
+            vfx: None,
+        }
+    }
+
+    /// Restarts the blink cycle whenever the cursor moves or changes mode
+    pub fn update_cursor(&mut self, cursor: Cursor) {
+        if cursor != self.cursor {
+            self.blink_status.reset();
+        }
+        self.cursor = cursor;
+    }
+
+    /// Runs once per frame before any window is drawn, so every window agrees on visibility
+    pub fn update_blink(&mut self) {
+        self.visible = self.blink_status.update(&self.cursor, Instant::now());
+    }
+
+    pub fn next_blink_transition(&self) -> Option<Instant> {
+        if self.cursor.enabled {
+            self.blink_status.next_transition(&self.cursor)
+        } else {
+            None
+        }
+    }
+
+    /// The cursor style, or the reversed default style when the mode has no highlight
+    fn cursor_style(&self, default_style: &Arc<Style>) -> Arc<Style> {
+        self.cursor.style.clone().unwrap_or_else(|| {
+            let mut style = Style::new(default_style.colors.clone());
+            style.reverse = true;
+            Arc::new(style)
+        })
+    }
+
+    /// The area the cursor covers once it has arrived in the cell at `position`
+    fn cursor_rect(&self, grid_render: &GridRender, position: (f32, f32)) -> Rect {
+        let (x, y) = position;
+        let font_dimensions = grid_render.font_dimensions;
+        let cell_count = if self.cursor.double_width { 2 } else { 1 };
+        let cell_width = (font_dimensions.width * cell_count) as f32;
+        let cell_height = font_dimensions.height as f32;
+        let percentage = self
+            .cursor
+            .cell_percentage
+            .unwrap_or(DEFAULT_BAR_PERCENTAGE);
+
+        match self.cursor.shape {
+            CursorShape::Block => Rect::from_xywh(x, y, cell_width, cell_height),
+            CursorShape::Vertical => {
+                let width = (font_dimensions.width as f32 * percentage).max(1.0);
+                Rect::from_xywh(x, y, width, cell_height)
+            }
+            CursorShape::Horizontal => {
+                let height = (cell_height * percentage).max(1.0);
+                Rect::from_xywh(x, y + cell_height - height, cell_width, height)
+            }
+        }
+    }
+
+    /// Sends every corner towards `destination`. Corners facing the direction of travel
+    /// arrive sooner than the ones behind them, which stretches the cursor into a trail.
+    fn animate_to(&mut self, destination: Rect, cell_width: f32, settings: &CursorSettings) {
+        let previous_center = self
+            .destination
+            .map(|previous| previous.center())
+            .unwrap_or_else(|| destination.center());
+        let travel = destination.center() - previous_center;
+        let short_jump = travel.y.abs() < 0.5 && travel.x.abs() <= cell_width * SHORT_JUMP_CELLS;
+        let trail_size = settings.trail_size.max(0.0).min(1.0);
+
+        for (corner, corner_destination) in self.corners.iter_mut().zip(&rect_corners(destination))
+        {
+            let length_multiplier = if short_jump || travel.length() == 0.0 {
+                1.0
+            } else {
+                let mut direction = *corner_destination - destination.center();
+                direction.normalize();
+                let mut travel_direction = travel;
+                travel_direction.normalize();
+                // 1 for a corner leading the way, -1 for the one at the very back
+                let alignment = direction.dot(travel_direction);
+                1.0 - trail_size * (alignment + 1.0) / 2.0
+            };
+            corner.animate_to(*corner_destination, length_multiplier);
+        }
+        self.destination = Some(destination);
+    }
+
+    /// Follows the vfx mode setting and starts the effect when the cursor moves to another
+    /// cell in insert mode. Every shape sits on the bottom left corner of its cell.
+    fn update_vfx(
+        &mut self,
+        settings: &CursorSettings,
+        previous_destination: Option<Rect>,
+        destination: Rect,
+        cell_size: (f32, f32),
+        dt: f32,
+    ) {
+        let mode = VfxMode::from_setting(&settings.vfx_mode);
+        if self.vfx.as_ref().map(|vfx| vfx.mode) != mode {
+            self.vfx = mode.map(CursorVfx::new);
+        }
+        let insert_mode = matches!(self.editor_mode, Some(EditorMode::Insert));
+        if let Some(vfx) = &mut self.vfx {
+            if let Some(previous) = previous_destination {
+                let moved =
+                    (previous.left, previous.bottom) != (destination.left, destination.bottom);
+                if moved && insert_mode {
+                    vfx.restart(settings, previous.center(), destination.center(), cell_size);
+                }
+            }
+            vfx.update(settings, dt);
+        }
+    }
+
+    fn jump_to(&mut self, destination: Rect) {
+        for (corner, corner_destination) in self.corners.iter_mut().zip(&rect_corners(destination))
+        {
+            corner.jump_to(*corner_destination);
+        }
+        self.destination = Some(destination);
+    }
+
+    /// Draws the cursor headed for the cell whose top left corner is at `position`. With
+    /// `animation_dt` the corners move on by that many seconds, without it they jump there.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_render: &mut GridRender,
+        position: (f32, f32),
+        animation_dt: Option<f32>,
+    ) {
+        let destination = self.cursor_rect(grid_render, position);
+        let style = self.cursor_style(&grid_render.default_style);
+        let cursor_color = style.background(&grid_render.default_style.colors);
+        let animating = match animation_dt {
+            Some(dt) => {
+                if self.destination.is_none() {
+                    self.jump_to(destination);
+                }
+                let settings = SETTINGS.get::<CursorSettings>();
+                let font_dimensions = grid_render.font_dimensions;
+                let cell_size = (font_dimensions.width as f32, font_dimensions.height as f32);
+                let previous_destination = self.destination;
+                self.animate_to(destination, cell_size.0, &settings);
+                self.update_vfx(&settings, previous_destination, destination, cell_size, dt);
+                if let Some(vfx) = &self.vfx {
+                    vfx.draw(&settings, canvas, cursor_color, cell_size);
+                }
+                let mut animating = false;
+                for corner in self.corners.iter_mut() {
+                    animating |= corner.update(&settings, dt);
+                }
+                animating
+            }
+            None => {
+                self.jump_to(destination);
+                // Positions in an external window mean nothing in the main one, so the
+                // cursor appears there without animating from an unrelated spot
+                self.destination = None;
+                false
+            }
+        };
+
+        if !self.cursor.enabled || !self.visible {
+            return;
+        }
+
+        let mut paint = Paint::new(cursor_color, None);
+        paint.set_anti_alias(animating);
+
+        match self.cursor.shape {
+            CursorShape::Block if self.focused && !animating => {
+                let mut characters = vec![self.cursor.character.clone()];
+                if self.cursor.double_width {
+                    characters.push(String::new());
+                }
+                let cell_count = characters.len() as u64;
+                let style = Some(style);
+                grid_render.draw_background(
+                    canvas,
+                    position,
+                    cell_count,
+                    &style,
+                    BlendMode::SrcOver,
+                );
+                grid_render.draw_foreground(canvas, position, &characters, &style);
+            }
+            CursorShape::Block if !self.focused => {
+                paint.set_style(PaintStyle::Stroke);
+                paint.set_stroke_width(1.0);
+                let outline = destination.with_inset((0.5, 0.5));
+                canvas.draw_rect(outline, &paint);
+            }
+            _ => {
+                let mut path = Path::new();
+                path.move_to(self.corners[0].current_position);
+                for corner in &self.corners[1..] {
+                    path.line_to(corner.current_position);
+                }
+                path.close();
+                canvas.draw_path(&path, &paint);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blinking_cursor(
+        blinkwait: Option<u64>,
+        blinkon: Option<u64>,
+        blinkoff: Option<u64>,
+    ) -> Cursor {
+        Cursor {
+            blinkwait,
+            blinkon,
+            blinkoff,
+            ..Cursor::new()
+        }
+    }
+
+    fn after(start: Instant, milliseconds: u64) -> Instant {
+        start + Duration::from_millis(milliseconds)
+    }
+
+    /// The next transition in milliseconds after `start`
+    fn next_transition(
+        blink_status: &BlinkStatus,
+        cursor: &Cursor,
+        start: Instant,
+    ) -> Option<u128> {
+        blink_status
+            .next_transition(cursor)
+            .map(|transition| (transition - start).as_millis())
+    }
+
+    #[test]
+    fn blinking_waits_then_alternates_off_and_on() {
+        let cursor = blinking_cursor(Some(500), Some(300), Some(200));
+        let mut blink_status = BlinkStatus::new();
+        let start = blink_status.last_transition;
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(500));
+
+        assert!(blink_status.update(&cursor, after(start, 499)));
+        assert_eq!(blink_status.state, BlinkState::Waiting);
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(500));
+
+        assert!(!blink_status.update(&cursor, after(start, 500)));
+        assert_eq!(blink_status.state, BlinkState::Off);
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(700));
+
+        assert!(blink_status.update(&cursor, after(start, 700)));
+        assert_eq!(blink_status.state, BlinkState::On);
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(1000));
+
+        assert!(!blink_status.update(&cursor, after(start, 1000)));
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(1200));
+    }
+
+    #[test]
+    fn late_updates_catch_up_on_every_missed_transition() {
+        let cursor = blinking_cursor(Some(500), Some(300), Some(200));
+        let mut blink_status = BlinkStatus::new();
+        let start = blink_status.last_transition;
+        // Off at 500, on at 700, off at 1000 and on again at 1200
+        assert!(!blink_status.update(&cursor, after(start, 1100)));
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(1200));
+        assert!(blink_status.update(&cursor, after(start, 1250)));
+        assert_eq!(next_transition(&blink_status, &cursor, start), Some(1500));
+    }
+
+    #[test]
+    fn missing_or_zero_timings_disable_blinking() {
+        let cursors = [
+            blinking_cursor(None, Some(300), Some(200)),
+            blinking_cursor(Some(500), None, Some(200)),
+            blinking_cursor(Some(500), Some(300), None),
+            blinking_cursor(Some(0), Some(300), Some(200)),
+            blinking_cursor(Some(500), Some(0), Some(200)),
+            blinking_cursor(Some(500), Some(300), Some(0)),
+        ];
+        for cursor in cursors.iter() {
+            let mut blink_status = BlinkStatus::new();
+            let start = blink_status.last_transition;
+            for milliseconds in [0, 500, 700, 10_000].iter() {
+                assert!(blink_status.update(cursor, after(start, *milliseconds)));
+                assert_eq!(blink_status.state, BlinkState::Waiting);
+                assert_eq!(blink_status.next_transition(cursor), None);
+            }
+        }
+    }
+
+    #[test]
+    fn disabling_blinking_shows_a_hidden_cursor() {
+        let cursor = blinking_cursor(Some(500), Some(300), Some(200));
+        let mut blink_status = BlinkStatus::new();
+        let start = blink_status.last_transition;
+        assert!(!blink_status.update(&cursor, after(start, 600)));
+        let steady_cursor = blinking_cursor(Some(500), Some(0), Some(200));
+        assert!(blink_status.update(&steady_cursor, after(start, 600)));
+    }
+
+    #[test]
+    fn reset_restarts_the_wait() {
+        let cursor = blinking_cursor(Some(500), Some(300), Some(200));
+        let mut blink_status = BlinkStatus::new();
+        let start = blink_status.last_transition;
+        assert!(!blink_status.update(&cursor, after(start, 600)));
+        blink_status.reset();
+        assert_eq!(blink_status.state, BlinkState::Waiting);
+        let reset_at = blink_status.last_transition;
+        assert!(blink_status.update(&cursor, after(reset_at, 499)));
+        assert_eq!(next_transition(&blink_status, &cursor, reset_at), Some(500));
+    }
+}
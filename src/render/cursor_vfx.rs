@@ -0,0 +1,305 @@
+use std::f32::consts::PI;
+
+use skia_safe::{Canvas, Color4f, Paint, PaintStyle, Point, Rect};
+
+use super::animation_utils::ease_out_expo;
+use super::settings::CursorSettings;
+
+// How fast railgun particles turn, in radians per second
+const RAILGUN_CURL: f32 = 1.0;
+// How many waves the railgun particles form along the path
+const RAILGUN_PHASE: f32 = 3.5;
+// How many lines a sonic boom or ripple grows to, measured from the cursor center
+const HIGHLIGHT_RADIUS_LINES: f32 = 2.5;
+// Long jumps would otherwise spawn a particle for every cell they cross
+const MAX_PARTICLES_PER_MOVE: usize = 200;
+// Fast typing keeps spawning before older particles die, the oldest go first past this
+const MAX_PARTICLES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VfxMode {
+    Railgun,
+    Torpedo,
+    PixieDust,
+    SonicBoom,
+    Ripple,
+}
+
+impl VfxMode {
+    pub fn from_setting(name: &str) -> Option<VfxMode> {
+        match name {
+            "railgun" => Some(VfxMode::Railgun),
+            "torpedo" => Some(VfxMode::Torpedo),
+            "pixiedust" => Some(VfxMode::PixieDust),
+            "sonicboom" => Some(VfxMode::SonicBoom),
+            "ripple" => Some(VfxMode::Ripple),
+            _ => None,
+        }
+    }
+}
+
+/// A xorshift generator, the particles only need to look random
+struct RngState {
+    state: u32,
+}
+
+impl RngState {
+    fn new() -> Self {
+        RngState { state: 0x9e37_79b9 }
+    }
+
+    /// A value in `0..1`
+    fn next_f32(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// A value in `-1..1`
+    fn next_signed(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
+
+struct Particle {
+    position: Point,
+    speed: Point,
+    rotation_speed: f32,
+    lifetime: f32,
+    initial_lifetime: f32,
+}
+
+impl Particle {
+    fn update(&mut self, dt: f32) {
+        let (sin, cos) = (self.rotation_speed * dt).sin_cos();
+        self.speed = Point::new(
+            self.speed.x * cos - self.speed.y * sin,
+            self.speed.x * sin + self.speed.y * cos,
+        );
+        self.position += self.speed * dt;
+        self.lifetime -= dt;
+    }
+
+    /// 1 when the particle is born, fading towards 0 as it dies
+    fn life_fraction(&self) -> f32 {
+        (self.lifetime / self.initial_lifetime).max(0.0)
+    }
+}
+
+/// Particles and highlights left behind by the cursor. Nothing is computed once every
+/// particle has died and the highlight has finished growing.
+pub struct CursorVfx {
+    pub mode: VfxMode,
+    particles: Vec<Particle>,
+    highlight_center: Point,
+    highlight_t: f32,
+    rng: RngState,
+}
+
+impl CursorVfx {
+    pub fn new(mode: VfxMode) -> Self {
+        CursorVfx {
+            mode,
+            particles: Vec::new(),
+            highlight_center: Point::default(),
+            highlight_t: 1.0,
+            rng: RngState::new(),
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.particles.is_empty() || self.highlight_t < 1.0
+    }
+
+    /// Starts the effect for the cursor moving from `start` to `destination`, both being the
+    /// centers of the cursor cell
+    pub fn restart(
+        &mut self,
+        settings: &CursorSettings,
+        start: Point,
+        destination: Point,
+        cell_size: (f32, f32),
+    ) {
+        match self.mode {
+            VfxMode::SonicBoom | VfxMode::Ripple => {
+                self.highlight_center = destination;
+                self.highlight_t = 0.0;
+            }
+            VfxMode::Railgun | VfxMode::Torpedo | VfxMode::PixieDust => {
+                self.emit_particles(settings, start, destination, cell_size);
+            }
+        }
+    }
+
+    fn emit_particles(
+        &mut self,
+        settings: &CursorSettings,
+        start: Point,
+        destination: Point,
+        (cell_width, cell_height): (f32, f32),
+    ) {
+        let travel = destination - start;
+        let travelled_cells = travel.length() / cell_width.max(1.0);
+        let count = match self.mode {
+            VfxMode::PixieDust => settings.vfx_particle_density.round(),
+            _ => (travelled_cells * settings.vfx_particle_density).round(),
+        }
+        .max(1.0)
+        .min(MAX_PARTICLES_PER_MOVE as f32) as usize;
+
+        let mut direction = travel;
+        if !direction.normalize() {
+            direction = Point::new(1.0, 0.0);
+        }
+        let normal = Point::new(-direction.y, direction.x);
+        let speed = settings.vfx_particle_speed * cell_height;
+        let lifetime = settings.vfx_particle_lifetime.max(0.0);
+
+        for index in 0..count {
+            let progress = index as f32 / count as f32;
+            let (position, speed, rotation_speed) = match self.mode {
+                VfxMode::Railgun => {
+                    let wave = (progress * travelled_cells * RAILGUN_PHASE * PI / 2.0).sin();
+                    (
+                        start + travel * progress,
+                        normal * wave * speed,
+                        RAILGUN_CURL,
+                    )
+                }
+                VfxMode::Torpedo => {
+                    let spread = normal * self.rng.next_signed() * 0.5;
+                    let backwards = direction * -(0.5 + self.rng.next_f32() * 0.5);
+                    (start + travel * progress, (backwards + spread) * speed, 0.0)
+                }
+                _ => {
+                    let offset = Point::new(
+                        self.rng.next_signed() * cell_width / 2.0,
+                        self.rng.next_f32() * cell_height / 2.0,
+                    );
+                    let falling =
+                        Point::new(self.rng.next_signed() * 0.5, 0.5 + self.rng.next_f32());
+                    (destination + offset, falling * speed * 0.5, 0.0)
+                }
+            };
+            let lifetime = lifetime * (0.5 + self.rng.next_f32() * 0.5);
+            self.particles.push(Particle {
+                position,
+                speed,
+                rotation_speed,
+                lifetime,
+                initial_lifetime: lifetime,
+            });
+        }
+        self.particles.retain(|particle| particle.lifetime > 0.0);
+        let overflow = self.particles.len().saturating_sub(MAX_PARTICLES);
+        self.particles.drain(..overflow);
+    }
+
+    /// Advances the effect by `dt` seconds, doing nothing once it has died out
+    pub fn update(&mut self, settings: &CursorSettings, dt: f32) {
+        if !self.is_alive() {
+            return;
+        }
+        if self.highlight_t < 1.0 {
+            let lifetime = settings.vfx_particle_lifetime;
+            self.highlight_t = if lifetime > 0.0 {
+                (self.highlight_t + dt / lifetime).min(1.0)
+            } else {
+                1.0
+            };
+        }
+        for particle in self.particles.iter_mut() {
+            particle.update(dt);
+        }
+        self.particles.retain(|particle| particle.lifetime > 0.0);
+    }
+
+    pub fn draw(
+        &self,
+        settings: &CursorSettings,
+        canvas: &mut Canvas,
+        color: Color4f,
+        (cell_width, cell_height): (f32, f32),
+    ) {
+        if !self.is_alive() {
+            return;
+        }
+        let opacity = (settings.vfx_opacity / 255.0).max(0.0).min(1.0);
+        let mut paint = Paint::new(color, None);
+        paint.set_anti_alias(true);
+
+        if self.highlight_t < 1.0 {
+            let growth = ease_out_expo(self.highlight_t);
+            let radius = growth * cell_height * HIGHLIGHT_RADIUS_LINES;
+            paint.set_alpha_f(opacity * (1.0 - growth));
+            if self.mode == VfxMode::Ripple {
+                paint.set_style(PaintStyle::Stroke);
+                paint.set_stroke_width(cell_height * 0.2);
+            }
+            canvas.draw_circle(self.highlight_center, radius, &paint);
+            paint.set_style(PaintStyle::Fill);
+        }
+
+        for particle in &self.particles {
+            let life = particle.life_fraction();
+            paint.set_alpha_f(opacity * life);
+            match self.mode {
+                VfxMode::Railgun => {
+                    let size = cell_width * 0.25 * life;
+                    let square = Rect::from_xywh(
+                        particle.position.x - size / 2.0,
+                        particle.position.y - size / 2.0,
+                        size,
+                        size,
+                    );
+                    canvas.draw_rect(square, &paint);
+                }
+                _ => {
+                    let radius = cell_width * 0.15 * life;
+                    canvas.draw_circle(particle.position, radius, &paint);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL_SIZE: (f32, f32) = (10.0, 20.0);
+
+    fn moved_vfx(mode: VfxMode, moves: usize, cells: f32) -> CursorVfx {
+        let settings = CursorSettings::default();
+        let mut vfx = CursorVfx::new(mode);
+        for _ in 0..moves {
+            let destination = Point::new(cells * CELL_SIZE.0, 0.0);
+            vfx.restart(&settings, Point::default(), destination, CELL_SIZE);
+        }
+        vfx
+    }
+
+    #[test]
+    fn long_jumps_spawn_a_bounded_number_of_particles() {
+        for mode in [VfxMode::Railgun, VfxMode::Torpedo].iter() {
+            let vfx = moved_vfx(*mode, 1, 10_000.0);
+            assert_eq!(vfx.particles.len(), MAX_PARTICLES_PER_MOVE, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn the_oldest_particles_make_room_for_new_ones() {
+        let vfx = moved_vfx(VfxMode::Railgun, 20, 10_000.0);
+        assert_eq!(vfx.particles.len(), MAX_PARTICLES);
+    }
+
+    #[test]
+    fn the_effect_dies_out() {
+        let settings = CursorSettings::default();
+        let mut vfx = moved_vfx(VfxMode::PixieDust, 3, 5.0);
+        assert!(vfx.is_alive());
+        vfx.update(&settings, settings.vfx_particle_lifetime + 1.0);
+        assert!(!vfx.is_alive());
+    }
+}
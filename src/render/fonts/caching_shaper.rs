@@ -1,16 +1,15 @@
 use log::trace;
 use lru::LruCache;
-use skia_safe::{TextBlob, TextBlobBuilder};
-use std::sync::Arc;
+use skia_safe::{RSXform, TextBlob, TextBlobBuilder};
+use std::{ops::Range, sync::Arc};
 use swash::{
     shape::ShapeContext,
     text::{
         cluster::{CharCluster, Parser, Token},
-        Script,
+        Codepoint, Script,
     },
     Metrics,
 };
-use unicode_segmentation::UnicodeSegmentation;
 
 use super::{
     font_loader::{FontKey, FontLoader, FontPair, FontSelection},
@@ -95,7 +94,8 @@ impl CachingShaper {
         }
     }
 
-    fn metrics(&mut self) -> Metrics {
+    /// Metrics of the regular font, scaled to the current pixel size
+    pub fn metrics(&mut self) -> Metrics {
         let font_pair = self.current_font_pair();
         let size = self.current_size();
         let shaper = self
@@ -112,33 +112,104 @@ impl CachingShaper {
         (metrics.average_width, font_height)
     }
 
+    /// The width glyphs are laid out with, which is the integer cell width of the grid
+    fn cell_width(&mut self) -> f32 {
+        self.font_base_dimensions().0.floor()
+    }
+
+    /// The script of the first character in the cell which belongs to one
+    fn cell_script(cell: &str) -> Option<Script> {
+        cell.chars()
+            .map(|character| character.script())
+            .find(|script| !matches!(script, Script::Common | Script::Inherited | Script::Unknown))
+    }
+
+    /// Splits the cells into runs of a single script, as clusters of complex scripts such as
+    /// Devanagari are only formed with the right script. Spaces, punctuation and empty cells
+    /// join the run they are in.
+    fn script_runs(cells: &[String]) -> Vec<(Range<usize>, Script)> {
+        let mut runs: Vec<(Range<usize>, Option<Script>)> = Vec::new();
+        for (index, cell) in cells.iter().enumerate() {
+            let script = Self::cell_script(cell);
+            match runs.last_mut() {
+                Some((run, run_script))
+                    if script.is_none() || run_script.is_none() || *run_script == script =>
+                {
+                    run.end = index + 1;
+                    *run_script = run_script.or(script);
+                }
+                _ => runs.push((index..index + 1, script)),
+            }
+        }
+        runs.into_iter()
+            .map(|(run, script)| (run, script.unwrap_or(Script::Latin)))
+            .collect()
+    }
+
+    /// Splits the `run` of cells into character clusters. Every token carries the index of
+    /// the grid cell it came from, so glyphs can be placed on their cell no matter how wide
+    /// the font draws them. Neovim sends a whole grapheme (with its combining marks or zero
+    /// width joiners) per cell, followed by an empty cell for double width characters.
+    fn parse_clusters(cells: &[String], run: Range<usize>, script: Script) -> Vec<CharCluster> {
+        let mut tokens = Vec::new();
+        // Offsets are into all the cells, which is what cell_span expects
+        let mut offset = cells[..run.start]
+            .iter()
+            .map(|cell| cell.len() as u32)
+            .sum();
+        for (cell_index, cell) in cells.iter().enumerate().take(run.end).skip(run.start) {
+            for c in cell.chars() {
+                tokens.push(Token {
+                    ch: c,
+                    offset,
+                    len: c.len_utf8() as u8,
+                    info: c.into(),
+                    data: cell_index as u32,
+                });
+                offset += c.len_utf8() as u32;
+            }
+        }
+        let mut parser = Parser::new(script, tokens.into_iter());
+        let mut cluster = CharCluster::new();
+        let mut clusters = Vec::new();
+        while parser.next(&mut cluster) {
+            clusters.push(cluster.to_owned());
+        }
+        clusters
+    }
+
+    /// The cells covered by the text at byte offsets `source` of the concatenated cells,
+    /// including the empty continuation cell of a trailing double width character
+    fn cell_span(cells: &[String], source: Range<u32>) -> Range<usize> {
+        let mut start = None;
+        let mut end = 0;
+        let mut offset = 0;
+        for (index, cell) in cells.iter().enumerate() {
+            let cell_start = offset;
+            offset += cell.len() as u32;
+            if cell.is_empty() {
+                if start.is_some() && end == index {
+                    end = index + 1;
+                }
+            } else if cell_start >= source.end {
+                break;
+            } else if offset > source.start {
+                start.get_or_insert(index);
+                end = index + 1;
+            }
+        }
+        match start {
+            Some(start) => start..end,
+            None => 0..1,
+        }
+    }
+
     fn build_clusters(
         &mut self,
-        text: &str,
+        cells: &[String],
         bold: bool,
         italic: bool,
-    ) -> Vec<(Vec<CharCluster>, Arc<FontPair>)> {
-        let mut cluster = CharCluster::new();
-        let mut character_index = 0;
-        let mut parser = Parser::new(
-            Script::Latin,
-            text.graphemes(true)
-                .enumerate()
-                .map(|(glyph_index, unicode_segmentation)| {
-                    unicode_segmentation.chars().map(move |c| {
-                        let token = Token {
-                            ch: c,
-                            offset: character_index as u32,
-                            len: c.len_utf8() as u8,
-                            info: c.into(),
-                            data: glyph_index as u32,
-                        };
-                        character_index += 1;
-                        token
-                    })
-                })
-                .flatten(),
-        );
+    ) -> Vec<(Vec<CharCluster>, Arc<FontPair>, Script)> {
         let mut font_fallback_keys = vec![FontKey {
             italic: self.options.italic || italic,
             bold: self.options.bold || bold,
@@ -151,53 +222,56 @@ impl CachingShaper {
         }));
         let mut results = Vec::new();
 
-        'cluster: while parser.next(&mut cluster) {
-            font_fallback_keys.push(FontKey {
-                italic,
-                bold,
-                font_selection: cluster.chars()[0].ch.into(),
-            });
-            let mut best = None;
-            for fallback_key in font_fallback_keys.iter().rev() {
-                if let Some(font_pair) = self.font_loader.get_or_load(fallback_key) {
-                    let charmap = font_pair.swash_font.as_ref().charmap();
-                    match cluster.map(|ch| charmap.map(ch)) {
-                        swash::text::cluster::Status::Complete => {
-                            results.push((cluster.to_owned(), font_pair.clone()));
-                            continue 'cluster;
-                        }
-                        swash::text::cluster::Status::Keep => {
-                            best = Some(font_pair);
+        for (run, script) in Self::script_runs(cells) {
+            'cluster: for mut cluster in Self::parse_clusters(cells, run, script) {
+                font_fallback_keys.push(FontKey {
+                    italic,
+                    bold,
+                    font_selection: cluster.chars()[0].ch.into(),
+                });
+                let mut best = None;
+                for fallback_key in font_fallback_keys.iter().rev() {
+                    if let Some(font_pair) = self.font_loader.get_or_load(fallback_key) {
+                        let charmap = font_pair.swash_font.as_ref().charmap();
+                        match cluster.map(|ch| charmap.map(ch)) {
+                            swash::text::cluster::Status::Complete => {
+                                results.push((cluster.to_owned(), font_pair.clone(), script));
+                                continue 'cluster;
+                            }
+                            swash::text::cluster::Status::Keep => {
+                                best = Some(font_pair);
+                            }
+                            swash::text::cluster::Status::Discard => {}
                         }
-                        swash::text::cluster::Status::Discard => {}
                     }
                 }
+                if let Some(best) = best {
+                    results.push((cluster.to_owned(), best.clone(), script));
+                }
+                font_fallback_keys.pop();
             }
-            if let Some(best) = best {
-                results.push((cluster.to_owned(), best.clone()));
-            }
-            font_fallback_keys.pop();
         }
 
+        // Every group is shaped in one go, so it has to share both the font and the script
         let mut grouped_results = Vec::new();
         let mut current_group = Vec::new();
-        let mut current_font_option = None;
-        for (cluster, font) in results {
-            if let Some(current_font) = current_font_option.clone() {
-                if current_font == font {
+        let mut current_key_option = None;
+        for (cluster, font, script) in results {
+            if let Some((current_font, current_script)) = current_key_option.clone() {
+                if current_font == font && current_script == script {
                     current_group.push(cluster);
                 } else {
-                    grouped_results.push((current_group, current_font));
+                    grouped_results.push((current_group, current_font, current_script));
                     current_group = vec![cluster];
-                    current_font_option = Some(font);
+                    current_key_option = Some((font, script));
                 }
             } else {
                 current_group = vec![cluster];
-                current_font_option = Some(font);
+                current_key_option = Some((font, script));
             }
         }
-        if !current_group.is_empty() {
-            grouped_results.push((current_group, current_font_option.unwrap()));
+        if let Some((font, script)) = current_key_option {
+            grouped_results.push((current_group, font, script));
         }
         grouped_results
     }
@@ -205,12 +279,13 @@ impl CachingShaper {
     fn shape(&mut self, cells: &[String], bold: bool, italic: bool) -> Vec<TextBlob> {
         let mut resulting_blobs = Vec::new();
         let current_size = self.current_size();
-        let text = cells.concat();
-        trace!("Shaping text: {}", text);
-        for (cluster_group, font_pair) in self.build_clusters(&text, bold, italic) {
+        let cell_width = self.cell_width();
+        trace!("Shaping text: {}", cells.concat());
+        for (cluster_group, font_pair, script) in self.build_clusters(cells, bold, italic) {
             let mut shaper = self
                 .shape_context
                 .builder(font_pair.swash_font.as_ref())
+                .script(script)
                 .size(current_size)
                 .build();
             let charmap = font_pair.swash_font.as_ref().charmap();
@@ -220,9 +295,24 @@ impl CachingShaper {
             }
             let mut glyph_data = Vec::new();
             shaper.shape_with(|glyph_cluster| {
+                let source = glyph_cluster.source;
+                let span = Self::cell_span(cells, source.start..source.end);
+                let cell_x = span.start as f32 * cell_width;
+                // Fallback glyphs of ZWJ sequences and conjuncts may be wider than their
+                // cells, which would put them under the background of the next cell
+                let available_width = span.len() as f32 * cell_width;
+                let cluster_width: f32 =
+                    glyph_cluster.glyphs.iter().map(|glyph| glyph.advance).sum();
+                let scale = if cluster_width > available_width {
+                    available_width / cluster_width
+                } else {
+                    1.0
+                };
+                let mut advance = 0.0;
                 for glyph in glyph_cluster.glyphs {
-                    let position = (glyph.x, glyph.y);
-                    glyph_data.push((glyph.id, position));
+                    let position = (cell_x + (advance + glyph.x) * scale, glyph.y * scale);
+                    glyph_data.push((glyph.id, RSXform::new(scale, 0.0, position)));
+                    advance += glyph.advance;
                 }
             });
 
@@ -231,10 +321,11 @@ impl CachingShaper {
             }
 
             let mut blob_builder = TextBlobBuilder::new();
-            let (glyphs, positions) = blob_builder.alloc_run_pos(&font_pair.skia_font, glyph_data.len(), None);
-            for (i, (glyph_id, glyph_position)) in glyph_data.iter().enumerate() {
+            let (glyphs, transforms) =
+                blob_builder.alloc_run_rsxform(&font_pair.skia_font, glyph_data.len());
+            for (i, (glyph_id, transform)) in glyph_data.iter().enumerate() {
                 glyphs[i] = *glyph_id;
-                positions[i] = (*glyph_position).into();
+                transforms[i] = *transform;
             }
             let blob = blob_builder.make();
             resulting_blobs.push(blob.expect("Could not create textblob"));
@@ -251,3 +342,122 @@ impl CachingShaper {
         self.blob_cache.get(&key).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_cells(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    /// The text of every cluster with the grid cell of each of its characters
+    fn clusters(cells: &[String]) -> Vec<(String, Vec<u32>)> {
+        CachingShaper::script_runs(cells)
+            .into_iter()
+            .flat_map(|(run, script)| CachingShaper::parse_clusters(cells, run, script))
+            .map(|cluster| {
+                let chars = cluster.chars();
+                (
+                    chars.iter().map(|c| c.ch).collect(),
+                    chars.iter().map(|c| c.data).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cjk_clusters_start_on_their_own_cell() {
+        let cells = to_cells(&["中", "", "文", ""]);
+        assert_eq!(
+            clusters(&cells),
+            vec![("中".to_owned(), vec![0]), ("文".to_owned(), vec![2])]
+        );
+        assert_eq!(CachingShaper::cell_span(&cells, 0..3), 0..2);
+        assert_eq!(CachingShaper::cell_span(&cells, 3..6), 2..4);
+    }
+
+    #[test]
+    fn emoji_zwj_sequences_are_one_cluster_on_two_cells() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let cells = to_cells(&[family, "", "a"]);
+        assert_eq!(
+            clusters(&cells),
+            vec![(family.to_owned(), vec![0; 5]), ("a".to_owned(), vec![2])]
+        );
+        assert_eq!(CachingShaper::cell_span(&cells, 0..18), 0..2);
+        assert_eq!(CachingShaper::cell_span(&cells, 18..19), 2..3);
+    }
+
+    #[test]
+    fn devanagari_conjuncts_span_the_cells_they_were_sent_in() {
+        // Neovim sends the consonant with its virama and the next consonant with its vowel
+        // sign in separate cells, which shape to a single conjunct
+        let cells = to_cells(&["न", "म", "स\u{94d}", "त\u{947}"]);
+        assert_eq!(
+            CachingShaper::script_runs(&cells),
+            vec![(0..4, Script::Devanagari)]
+        );
+        assert_eq!(
+            clusters(&cells),
+            vec![
+                ("न".to_owned(), vec![0]),
+                ("म".to_owned(), vec![1]),
+                ("स\u{94d}त\u{947}".to_owned(), vec![2, 2, 3, 3]),
+            ]
+        );
+        assert_eq!(CachingShaper::cell_span(&cells, 6..18), 2..4);
+    }
+
+    #[test]
+    fn combining_marks_stay_on_their_cell() {
+        let cells = to_cells(&["e\u{301}", "x"]);
+        assert_eq!(
+            clusters(&cells),
+            vec![
+                ("e\u{301}".to_owned(), vec![0, 0]),
+                ("x".to_owned(), vec![1])
+            ]
+        );
+        assert_eq!(CachingShaper::cell_span(&cells, 0..3), 0..1);
+    }
+
+    #[test]
+    fn mixed_script_lines_are_shaped_per_script_run() {
+        let cells = to_cells(&["a", " ", "न", "स\u{94d}", "त\u{947}", ")", "b", "中", ""]);
+        assert_eq!(
+            CachingShaper::script_runs(&cells),
+            vec![
+                (0..2, Script::Latin),
+                (2..6, Script::Devanagari),
+                (6..7, Script::Latin),
+                (7..9, Script::Han),
+            ]
+        );
+        // The conjunct only forms because its run is parsed as Devanagari
+        assert_eq!(
+            clusters(&cells)[2..5],
+            [
+                ("न".to_owned(), vec![2]),
+                ("स\u{94d}त\u{947}".to_owned(), vec![3, 3, 4, 4]),
+                (")".to_owned(), vec![5]),
+            ]
+        );
+        assert_eq!(CachingShaper::cell_span(&cells, 5..17), 3..5);
+    }
+
+    #[test]
+    fn cells_without_a_script_join_the_run_they_are_in() {
+        let cells = to_cells(&[" ", "-", "न", "1"]);
+        assert_eq!(
+            CachingShaper::script_runs(&cells),
+            vec![(0..4, Script::Devanagari)]
+        );
+        let cells = to_cells(&[" ", "1"]);
+        assert_eq!(
+            CachingShaper::script_runs(&cells),
+            vec![(0..2, Script::Latin)]
+        );
+        assert!(CachingShaper::script_runs(&[]).is_empty());
+    }
+}
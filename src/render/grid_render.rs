@@ -1,14 +1,29 @@
-use skia_safe::{Canvas, Paint, colors};
+use skia_safe::{BlendMode, Canvas, Paint, PaintStyle, Path, PathEffect, Rect, colors};
 use std::sync::Arc;
-use crate::editor::{Colors, Style};
+use crate::editor::{Colors, Style, UnderlineStyle};
+use crate::window::Dimensions;
 
 use super::fonts::caching_shaper::CachingShaper;
 
+/// Vertical offsets in pixels from the top of a line, including the linespace above the text
+#[derive(Clone, Copy, Default)]
+struct LineMetrics {
+    baseline: f32,
+    underline_position: f32,
+    strikeout_position: f32,
+    stroke_size: f32,
+}
+
 pub struct GridRender {
     pub shaper: CachingShaper,
     pub paint: Paint,
     pub default_style: Arc<Style>,
     pub scale_factor: f64,
+    pub font_dimensions: Dimensions,
+    /// Opacity of the default background, the window transparency setting
+    pub transparency: f32,
+    linespace: u64,
+    line_metrics: LineMetrics,
 }
 
 impl GridRender {
@@ -21,11 +36,205 @@ impl GridRender {
             Some(colors::BLACK),
             Some(colors::GREY),
         )));
-        GridRender {
+        let mut grid_render = GridRender {
             shaper,
             paint,
             default_style,
             scale_factor,
+            font_dimensions: Dimensions::default(),
+            transparency: 1.0,
+            linespace: 0,
+            line_metrics: LineMetrics::default(),
+        };
+        grid_render.update_font_dimensions();
+        grid_render
+    }
+
+    pub fn update_font(&mut self, guifont_setting: &str) {
+        self.shaper.update_font(guifont_setting);
+        self.update_font_dimensions();
+    }
+
+    pub fn update_linespace(&mut self, linespace: u64) {
+        self.linespace = linespace;
+        self.update_font_dimensions();
+    }
+
+    pub fn handle_scale_factor_update(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.shaper.update_scale_factor(scale_factor as f32);
+        self.update_font_dimensions();
+    }
+
+    fn resolve_style(&self, style: &Option<Arc<Style>>) -> Arc<Style> {
+        style.clone().unwrap_or_else(|| self.default_style.clone())
+    }
+
+    /// Fills `cell_count` cells starting at the pixel `position` with the background of `style`.
+    /// The default background is drawn with the window transparency.
+    pub fn draw_background(
+        &mut self,
+        canvas: &mut Canvas,
+        position: (f32, f32),
+        cell_count: u64,
+        style: &Option<Arc<Style>>,
+        blend_mode: BlendMode,
+    ) {
+        let style = self.resolve_style(style);
+        let (x, y) = position;
+        let width = (cell_count * self.font_dimensions.width) as f32;
+        let height = self.font_dimensions.height as f32;
+        let region = Rect::from_xywh(x, y, width, height);
+        let mut background = style.background(&self.default_style.colors);
+        if style.has_default_background() {
+            background.a *= self.transparency;
+        }
+        self.paint.set_color4f(background, None);
+        self.paint.set_blend_mode(blend_mode);
+        canvas.draw_rect(region, &self.paint);
+        self.paint.set_blend_mode(BlendMode::SrcOver);
+    }
+
+    /// Shapes the text of `cells` and draws it on the baseline of the line at `position`
+    pub fn draw_foreground(
+        &mut self,
+        canvas: &mut Canvas,
+        position: (f32, f32),
+        cells: &[String],
+        style: &Option<Arc<Style>>,
+    ) {
+        if cells.iter().all(|cell| cell.trim().is_empty()) {
+            return;
+        }
+        let style = self.resolve_style(style);
+        let (x, y) = position;
+        let baseline = y + self.line_metrics.baseline;
+        self.paint
+            .set_color4f(style.foreground(&self.default_style.colors), None);
+        for blob in self.shaper.shape_cached(cells, style.bold, style.italic) {
+            canvas.draw_text_blob(blob, (x, baseline), &self.paint);
+        }
+    }
+
+    /// Draws the underline of any style and the strikethrough of `style` across `cell_count` cells
+    pub fn draw_decorations(
+        &mut self,
+        canvas: &mut Canvas,
+        position: (f32, f32),
+        cell_count: u64,
+        style: &Option<Arc<Style>>,
+    ) {
+        let style = self.resolve_style(style);
+        if style.underline.is_none() && !style.strikethrough {
+            return;
+        }
+        let (x, y) = position;
+        let width = (cell_count * self.font_dimensions.width) as f32;
+        let metrics = self.line_metrics;
+
+        let mut paint = Paint::new(style.special(&self.default_style.colors), None);
+        paint.set_anti_alias(true);
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width(metrics.stroke_size);
+
+        let underline_y = y + metrics.underline_position;
+        match style.underline {
+            Some(UnderlineStyle::Underline) => {
+                canvas.draw_line((x, underline_y), (x + width, underline_y), &paint);
+            }
+            Some(UnderlineStyle::UnderDouble) => {
+                let second_y = underline_y + metrics.stroke_size * 2.0;
+                canvas.draw_line((x, underline_y), (x + width, underline_y), &paint);
+                canvas.draw_line((x, second_y), (x + width, second_y), &paint);
+            }
+            Some(UnderlineStyle::UnderCurl) => {
+                self.draw_undercurl(canvas, position, width, &paint);
+            }
+            Some(UnderlineStyle::UnderDotted) => {
+                let dot = metrics.stroke_size;
+                Self::draw_dashed_line(canvas, (x, underline_y), width, [dot, dot], &paint);
+            }
+            Some(UnderlineStyle::UnderDashed) => {
+                let dash = metrics.stroke_size * 3.0;
+                Self::draw_dashed_line(canvas, (x, underline_y), width, [dash, dash], &paint);
+            }
+            None => {}
         }
+        if style.strikethrough {
+            let line_y = y + metrics.strikeout_position;
+            canvas.draw_line((x, line_y), (x + width, line_y), &paint);
+        }
+    }
+
+    /// The dash pattern is anchored at the canvas origin, so it continues across runs
+    fn draw_dashed_line(
+        canvas: &mut Canvas,
+        start: (f32, f32),
+        width: f32,
+        intervals: [f32; 2],
+        paint: &Paint,
+    ) {
+        let (x, y) = start;
+        let phase = x % (intervals[0] + intervals[1]);
+        let mut paint = paint.clone();
+        paint.set_path_effect(PathEffect::dash(&intervals, phase));
+        canvas.draw_line((x, y), (x + width, y), &paint);
+    }
+
+    /// A wave with one period per cell, clipped to the cells so neighbouring runs line up
+    fn draw_undercurl(&self, canvas: &mut Canvas, position: (f32, f32), width: f32, paint: &Paint) {
+        let (x, top) = position;
+        let y = top + self.line_metrics.underline_position;
+        let period = self.font_dimensions.width.max(1) as f32;
+        let amplitude = self.line_metrics.stroke_size * 1.5;
+        let half_period = period / 2.0;
+
+        let mut path = Path::new();
+        path.move_to((x, y));
+        let mut wave_x = x;
+        let mut direction = -1.0;
+        while wave_x < x + width {
+            // A quadratic curve peaks halfway to its control point
+            let control = (wave_x + half_period / 2.0, y + direction * amplitude * 2.0);
+            path.quad_to(control, (wave_x + half_period, y));
+            wave_x += half_period;
+            direction = -direction;
+        }
+
+        let line_height = self.font_dimensions.height as f32;
+        canvas.save();
+        canvas.clip_rect(Rect::from_xywh(x, top, width, line_height), None, None);
+        canvas.draw_path(&path, paint);
+        canvas.restore();
+    }
+
+    fn update_font_dimensions(&mut self) {
+        let (font_width, font_height) = self.shaper.font_base_dimensions();
+        let linespace = self.linespace as f32 * self.scale_factor as f32;
+        self.font_dimensions = Dimensions::from((font_width, font_height + linespace));
+        self.update_line_metrics(linespace);
+    }
+
+    /// Swash metrics are in pixels with y pointing up from the baseline, and give the top
+    /// of each stroke. Decorations are drawn centered on their stroke instead.
+    fn update_line_metrics(&mut self, linespace: f32) {
+        let metrics = self.shaper.metrics();
+        let baseline = (linespace / 2.0 + metrics.ascent).round();
+        let stroke_size = if metrics.stroke_size > 0.0 {
+            metrics.stroke_size.max(1.0)
+        } else {
+            (self.shaper.current_size() / 14.0).max(1.0)
+        };
+        let strikeout_offset = if metrics.strikeout_offset > 0.0 {
+            metrics.strikeout_offset
+        } else {
+            metrics.x_height / 2.0 + stroke_size / 2.0
+        };
+        self.line_metrics = LineMetrics {
+            baseline,
+            underline_position: baseline - metrics.underline_offset + stroke_size / 2.0,
+            strikeout_position: baseline - strikeout_offset + stroke_size / 2.0,
+            stroke_size,
+        };
     }
 }
@@ -1,17 +1,33 @@
+mod animation_utils;
+mod cursor_renderer;
+mod cursor_vfx;
 mod fonts;
 mod grid_render;
+mod rendered_window;
+mod settings;
 
-use std::sync::mpsc::Receiver;
+use log::trace;
+use std::collections::HashMap;
+use std::sync::{mpsc::Receiver, Arc};
+use std::time::{Duration, Instant};
 
-use skia_safe::Canvas;
+use skia_safe::{colors, Canvas, Color4f, Paint, PaintStyle, Rect};
 
 use crate::editor::DrawCommand;
+use crate::settings::SETTINGS;
+use crate::window::{Dimensions, WindowSettings};
 
-use self::grid_render::GridRender;
+use self::{
+    cursor_renderer::CursorRenderer, grid_render::GridRender, rendered_window::RenderedWindow,
+};
+
+pub use self::settings::{CursorSettings, RenderSettings};
 
 pub struct Render {
     pub batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
     pub grid_render: GridRender,
+    pub cursor_renderer: CursorRenderer,
+    rendered_windows: HashMap<u64, RenderedWindow>,
     font_changed: bool,
 }
 
@@ -23,27 +39,187 @@ impl Render {
         Render {
             batched_draw_command_receiver,
             grid_render: GridRender::new(scale_factor),
+            cursor_renderer: CursorRenderer::new(),
+            rendered_windows: HashMap::new(),
             font_changed: false,
         }
     }
 
     pub fn draw_frame(&mut self, root_canvas: &mut Canvas, dt: f32) -> bool {
-        let draw_commands = self
+        let batches = self
             .batched_draw_command_receiver
             .try_iter()
-            .map(|batch| batch.into_iter())
-            .flatten()
             .collect::<Vec<_>>();
         self.font_changed = false;
-        for draw_command in draw_commands {
-            self.handle_draw_command(root_canvas, draw_command);
+        for batch in batches {
+            for draw_command in batch {
+                self.handle_draw_command(root_canvas, draw_command);
+            }
+            for window in self.rendered_windows.values_mut() {
+                window.finish_batch();
+            }
+        }
+
+        // Read every frame so changing g:xvim_transparency applies right away
+        let transparency = SETTINGS.get::<WindowSettings>().transparency;
+        self.grid_render.transparency = transparency.max(0.0).min(1.0);
+
+        let render_settings = SETTINGS.get::<RenderSettings>();
+        for window in self.rendered_windows.values_mut() {
+            window.animate(dt, &render_settings);
+        }
+
+        root_canvas.clear(self.default_background().to_color());
+        let mut windows: Vec<&RenderedWindow> = self
+            .rendered_windows
+            .values()
+            .filter(|window| !window.external && !window.hidden)
+            .collect();
+        // Regular windows have no floating order and sort first, so floats land on top
+        windows.sort_by_key(|window| (window.floating_order, window.grid_id));
+
+        let padding = Self::padding();
+        let font_dimensions = self.grid_render.font_dimensions;
+        let window_origin = |window: &RenderedWindow| {
+            let rect = window.current_rect();
+            (
+                padding.0 + rect.left * font_dimensions.width as f32,
+                padding.1 + rect.top * font_dimensions.height as f32,
+            )
+        };
+        for window in windows {
+            let origin = window_origin(window);
+            window.draw(root_canvas, &mut self.grid_render, origin, &render_settings);
+        }
+
+        self.cursor_renderer.update_blink();
+        let cursor_window = self
+            .rendered_windows
+            .get(&self.cursor_renderer.cursor.parent_window_id);
+        if let Some(window) = cursor_window {
+            if !window.external && !window.hidden {
+                let origin = window_origin(window);
+                self.draw_cursor(root_canvas, origin, Some(dt));
+            }
         }
         self.font_changed
     }
 
+    /// Draws the cursor in the grid whose top left corner is at `grid_origin`
+    fn draw_cursor(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_origin: (f32, f32),
+        animation_dt: Option<f32>,
+    ) {
+        let font_dimensions = self.grid_render.font_dimensions;
+        let (column, row) = self.cursor_renderer.cursor.grid_position;
+        let position = (
+            grid_origin.0 + (column * font_dimensions.width) as f32,
+            grid_origin.1 + (row * font_dimensions.height) as f32,
+        );
+        self.cursor_renderer
+            .draw(canvas, &mut self.grid_render, position, animation_dt);
+    }
+
+    /// When the blinking cursor next appears or disappears, so an idle loop can wake up for it
+    pub fn next_blink_transition(&self) -> Option<Instant> {
+        self.cursor_renderer.next_blink_transition()
+    }
+
+    /// The default background with the window transparency, which shows what is behind the
+    /// window wherever no highlight sets its own background
+    fn default_background(&self) -> Color4f {
+        let background = self
+            .grid_render
+            .default_style
+            .colors
+            .background
+            .unwrap_or(colors::BLACK);
+        Color4f {
+            a: background.a * self.grid_render.transparency,
+            ..background
+        }
+    }
+
+    fn padding() -> (f32, f32) {
+        let settings = SETTINGS.get::<WindowSettings>();
+        (settings.padding_left as f32, settings.padding_top as f32)
+    }
+
+    /// Draws a grid which lives in its own OS window
+    pub fn draw_external_window(&mut self, grid_id: u64, canvas: &mut Canvas) {
+        canvas.clear(self.default_background().to_color());
+        if let Some(rendered_window) = self.rendered_windows.get(&grid_id) {
+            if rendered_window.external && !rendered_window.hidden {
+                trace!("drawing external grid {}", rendered_window.grid_id);
+                let render_settings = SETTINGS.get::<RenderSettings>();
+                rendered_window.draw(
+                    canvas,
+                    &mut self.grid_render,
+                    Self::padding(),
+                    &render_settings,
+                );
+                if self.cursor_renderer.cursor.parent_window_id == grid_id {
+                    self.draw_cursor(canvas, Self::padding(), None);
+                }
+            }
+        }
+    }
+
+    /// Draws a spinning arc in the top right corner while neovim is busy
+    pub fn draw_busy_spinner(&self, canvas: &mut Canvas, busy_duration: Duration) {
+        let size = self.grid_render.font_dimensions.height as f32;
+        let canvas_width = canvas.base_layer_size().width as f32;
+        let margin = size / 2.0;
+        let bounds = Rect::from_xywh(canvas_width - size - margin, margin, size, size);
+
+        let default_style = &self.grid_render.default_style;
+        let mut paint = Paint::new(default_style.foreground(&default_style.colors), None);
+        paint.set_anti_alias(true);
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width((size / 8.0).max(1.0));
+
+        let start_angle = (busy_duration.as_secs_f32() * 360.0) % 360.0;
+        canvas.draw_arc(bounds, start_angle, 270.0, false, &paint);
+    }
+
+    pub fn font_dimensions(&self) -> Dimensions {
+        self.grid_render.font_dimensions
+    }
+
+    pub fn handle_scale_factor_update(&mut self, scale_factor: f64) {
+        self.grid_render.handle_scale_factor_update(scale_factor);
+    }
+
     fn handle_draw_command(&mut self, _root_canvas: &mut Canvas, draw_command: DrawCommand) {
         match draw_command {
-            _ => {}
+            DrawCommand::FontChanged(guifont) => {
+                self.grid_render.update_font(&guifont);
+                self.font_changed = true;
+            }
+            DrawCommand::LineSpaceChanged(linespace) => {
+                self.grid_render.update_linespace(linespace);
+                self.font_changed = true;
+            }
+            DrawCommand::DefaultStyleChanged(style) => {
+                self.grid_render.default_style = Arc::new(style);
+            }
+            DrawCommand::Window { grid_id, command } => {
+                self.rendered_windows
+                    .entry(grid_id)
+                    .or_insert_with(|| RenderedWindow::new(grid_id))
+                    .handle_window_draw_command(command);
+            }
+            DrawCommand::CloseWindow(grid_id) => {
+                self.rendered_windows.remove(&grid_id);
+            }
+            DrawCommand::UpdateCursor(cursor) => {
+                self.cursor_renderer.update_cursor(cursor);
+            }
+            DrawCommand::ModeChanged(mode) => {
+                self.cursor_renderer.editor_mode = Some(mode);
+            }
         }
     }
 }
@@ -0,0 +1,462 @@
+use skia_safe::{
+    canvas::SaveLayerRec, image_filters, BlendMode, BlurStyle, Canvas, ClipOp, MaskFilter, Paint,
+    RRect, Rect,
+};
+
+use crate::editor::{CharacterGrid, GridCell, WindowDrawCommand};
+
+use super::animation_utils::{ease_out_expo, lerp};
+use super::grid_render::GridRender;
+use super::settings::RenderSettings;
+
+/// The grid as it was before a scroll, which slides out while the new content slides in
+struct ScrollAnimation {
+    snapshot: CharacterGrid,
+    /// How many rows below the current content the snapshot belongs
+    snapshot_rows: f32,
+    start_offset: f32,
+    t: f32,
+}
+
+/// Where the window was shown when it last moved or resized, in grid cells
+struct PositionAnimation {
+    start_rect: Rect,
+    t: f32,
+}
+
+/// Advances an animation of `animation_length` seconds by `dt`, returning the new progress
+fn advance(t: f32, dt: f32, animation_length: f32) -> f32 {
+    if animation_length > 0.0 {
+        (t + dt / animation_length).min(1.0)
+    } else {
+        1.0
+    }
+}
+
+pub struct RenderedWindow {
+    pub grid_id: u64,
+    pub grid_position: (f64, f64),
+    pub floating_order: Option<(u64, u64)>,
+    pub external: bool,
+    pub hidden: bool,
+    pub anchored: bool,
+    separator: Option<GridCell>,
+    grid: CharacterGrid,
+    // Taken at the first grid_scroll of a batch, in case a viewport change follows
+    pending_snapshot: Option<CharacterGrid>,
+    scroll_animation: Option<ScrollAnimation>,
+    position_animation: Option<PositionAnimation>,
+    // Windows appear in place the first time they are positioned
+    positioned: bool,
+}
+
+/// Splits a row into runs of neighbouring cells sharing a style, with the column each starts at
+fn style_runs(cells: &[GridCell]) -> Vec<(u64, &[GridCell])> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (index, cell) in cells.iter().enumerate().skip(1) {
+        if cell.style != cells[start].style {
+            runs.push((start as u64, &cells[start..index]));
+            start = index;
+        }
+    }
+    if start < cells.len() {
+        runs.push((start as u64, &cells[start..]));
+    }
+    runs
+}
+
+impl RenderedWindow {
+    pub fn new(grid_id: u64) -> Self {
+        RenderedWindow {
+            grid_id,
+            grid_position: (0.0, 0.0),
+            floating_order: None,
+            external: false,
+            hidden: false,
+            anchored: false,
+            separator: None,
+            grid: CharacterGrid::new((0, 0)),
+            pending_snapshot: None,
+            scroll_animation: None,
+            position_animation: None,
+            positioned: false,
+        }
+    }
+
+    pub fn handle_window_draw_command(&mut self, command: WindowDrawCommand) {
+        match command {
+            WindowDrawCommand::Position {
+                grid_position,
+                width,
+                height,
+                floating_order,
+                external,
+                anchored,
+                separator,
+            } => {
+                let previous_rect = self.current_rect();
+                let was_shown = self.positioned && !self.hidden && !self.external;
+                self.grid_position = grid_position;
+                self.floating_order = floating_order;
+                self.external = external;
+                self.anchored = anchored;
+                self.separator = separator;
+                self.hidden = false;
+                self.positioned = true;
+                if (width, height) != (self.grid.width, self.grid.height) {
+                    self.grid.resize((width, height));
+                    self.scroll_animation = None;
+                }
+                if was_shown && !external && self.target_rect() != previous_rect {
+                    self.position_animation = Some(PositionAnimation {
+                        start_rect: previous_rect,
+                        t: 0.0,
+                    });
+                }
+            }
+            WindowDrawCommand::Line { row, cells } => {
+                self.grid.set_row(row, cells);
+            }
+            WindowDrawCommand::Scroll {
+                top,
+                bottom,
+                left,
+                right,
+                rows,
+                columns,
+            } => {
+                if self.pending_snapshot.is_none() {
+                    self.pending_snapshot = Some(self.grid.clone());
+                }
+                self.grid
+                    .scroll_region(top, bottom, left, right, rows, columns);
+            }
+            WindowDrawCommand::Clear => {
+                self.grid.clear();
+                self.scroll_animation = None;
+            }
+            WindowDrawCommand::Hide => {
+                self.hidden = true;
+            }
+            WindowDrawCommand::Viewport { scroll_delta } => {
+                if let Some(snapshot) = self.pending_snapshot.take() {
+                    if scroll_delta != 0.0 {
+                        self.start_scroll_animation(snapshot, scroll_delta as f32);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Grid scrolls that no viewport change followed within their batch were redraws
+    pub fn finish_batch(&mut self) {
+        self.pending_snapshot = None;
+    }
+
+    /// A scroll arriving mid-animation starts from where the content is currently shown
+    fn start_scroll_animation(&mut self, snapshot: CharacterGrid, scroll_delta: f32) {
+        let height = self.grid.height as f32;
+        let start_offset = (self.scroll_offset() + scroll_delta)
+            .max(-height)
+            .min(height);
+        self.scroll_animation = Some(ScrollAnimation {
+            snapshot,
+            snapshot_rows: scroll_delta,
+            start_offset,
+            t: 0.0,
+        });
+    }
+
+    /// Advances the scroll and position animations by `dt` seconds
+    pub fn animate(&mut self, dt: f32, settings: &RenderSettings) {
+        if let Some(scroll_animation) = &mut self.scroll_animation {
+            scroll_animation.t = advance(scroll_animation.t, dt, settings.scroll_animation_length);
+            if scroll_animation.t >= 1.0 {
+                self.scroll_animation = None;
+            }
+        }
+        if let Some(position_animation) = &mut self.position_animation {
+            position_animation.t =
+                advance(position_animation.t, dt, settings.position_animation_length);
+            if position_animation.t >= 1.0 {
+                self.position_animation = None;
+            }
+        }
+    }
+
+    /// Where neovim placed the window, in grid cells
+    fn target_rect(&self) -> Rect {
+        let (left, top) = self.grid_position;
+        Rect::from_xywh(
+            left as f32,
+            top as f32,
+            self.grid.width as f32,
+            self.grid.height as f32,
+        )
+    }
+
+    /// Where the window is shown right now, in grid cells. While it moves or resizes the
+    /// grid is drawn at its new size and clipped to this rect.
+    pub fn current_rect(&self) -> Rect {
+        let target = self.target_rect();
+        match &self.position_animation {
+            Some(position_animation) => {
+                let progress = ease_out_expo(position_animation.t);
+                let start = position_animation.start_rect;
+                Rect::new(
+                    lerp(start.left, target.left, progress),
+                    lerp(start.top, target.top, progress),
+                    lerp(start.right, target.right, progress),
+                    lerp(start.bottom, target.bottom, progress),
+                )
+            }
+            None => target,
+        }
+    }
+
+    /// How many rows below its place the content is drawn
+    fn scroll_offset(&self) -> f32 {
+        self.scroll_animation
+            .as_ref()
+            .map_or(0.0, |scroll_animation| {
+                scroll_animation.start_offset * (1.0 - ease_out_expo(scroll_animation.t))
+            })
+    }
+
+    /// Draws the window with its top left corner at `origin`, including the content that is
+    /// sliding out of view while it scrolls
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        grid_render: &mut GridRender,
+        origin: (f32, f32),
+        settings: &RenderSettings,
+    ) {
+        let font_dimensions = grid_render.font_dimensions;
+        let line_height = font_dimensions.height as f32;
+        let size = self.current_rect().size();
+        let bounds = Rect::from_xywh(
+            origin.0,
+            origin.1,
+            size.width * font_dimensions.width as f32,
+            size.height * line_height,
+        );
+        let offset = self.scroll_offset() * line_height;
+        let corner_radius = if self.anchored {
+            settings.floating_corner_radius.max(0.0)
+        } else {
+            0.0
+        };
+        let outline = RRect::new_rect_xy(bounds, corner_radius, corner_radius);
+
+        if self.anchored && settings.floating_shadow {
+            draw_shadow(canvas, &outline, settings);
+        }
+        let save_count = canvas.save();
+        canvas.clip_rrect(outline, None, Some(corner_radius > 0.0));
+        if self.anchored && settings.floating_blur_amount > 0.0 {
+            let sigma = settings.floating_blur_amount;
+            if let Some(blur) = image_filters::blur((sigma, sigma), None, None, None) {
+                // The layer starts out as the blurred content behind the float
+                canvas.save_layer(&SaveLayerRec::default().bounds(&bounds).backdrop(&blur));
+            }
+        }
+        // Regular windows tile the canvas, so their backgrounds replace the translucent clear
+        // instead of stacking on it. Floats blend over the windows below them.
+        let blend_mode = if self.floating_order.is_none() {
+            BlendMode::Src
+        } else {
+            BlendMode::SrcOver
+        };
+        if let Some(scroll_animation) = &self.scroll_animation {
+            let snapshot_top = origin.1 + offset - scroll_animation.snapshot_rows * line_height;
+            draw_grid(
+                &scroll_animation.snapshot,
+                canvas,
+                grid_render,
+                (origin.0, snapshot_top),
+                blend_mode,
+            );
+        }
+        draw_grid(
+            &self.grid,
+            canvas,
+            grid_render,
+            (origin.0, origin.1 + offset),
+            blend_mode,
+        );
+        canvas.restore_to_count(save_count);
+
+        if let Some(separator) = &self.separator {
+            let position = (origin.0, origin.1 - line_height);
+            let text = vec![separator.text.clone(); self.grid.width as usize];
+            grid_render.draw_background(
+                canvas,
+                position,
+                self.grid.width,
+                &separator.style,
+                blend_mode,
+            );
+            grid_render.draw_foreground(canvas, position, &text, &separator.style);
+        }
+    }
+}
+
+/// A blurred copy of the window outline, offset along the shadow angle. The area under the
+/// window is left out so translucent floats do not show their own shadow.
+fn draw_shadow(canvas: &mut Canvas, outline: &RRect, settings: &RenderSettings) {
+    let light_radius = settings.floating_light_radius.max(0.0);
+    let angle = settings.floating_shadow_angle.to_radians();
+    let distance = light_radius / 2.0;
+    let shadow_bounds = outline
+        .rect()
+        .with_offset((angle.cos() * distance, angle.sin() * distance));
+    let corner_radius = outline.simple_radii().x;
+    let shadow = RRect::new_rect_xy(shadow_bounds, corner_radius, corner_radius);
+
+    let mut paint = Paint::new(settings.shadow_color(), None);
+    paint.set_anti_alias(true);
+    if light_radius > 0.0 {
+        paint.set_mask_filter(MaskFilter::blur(
+            BlurStyle::Normal,
+            light_radius / 2.0,
+            None,
+        ));
+    }
+
+    canvas.save();
+    canvas.clip_rrect(outline, ClipOp::Difference, Some(true));
+    canvas.draw_rrect(shadow, &paint);
+    canvas.restore();
+}
+
+/// Draws every cell with the grid's top left corner at `origin`. All backgrounds are
+/// drawn before any text so glyphs reaching into the next run are not painted over.
+fn draw_grid(
+    grid: &CharacterGrid,
+    canvas: &mut Canvas,
+    grid_render: &mut GridRender,
+    origin: (f32, f32),
+    blend_mode: BlendMode,
+) {
+    let font_dimensions = grid_render.font_dimensions;
+    let cell_position = |column: u64, row: u64| {
+        (
+            origin.0 + (column * font_dimensions.width) as f32,
+            origin.1 + (row * font_dimensions.height) as f32,
+        )
+    };
+
+    for row in 0..grid.height {
+        if let Some(cells) = grid.row(row) {
+            for (column, run) in style_runs(cells) {
+                let position = cell_position(column, row);
+                let cell_count = run.len() as u64;
+                grid_render.draw_background(
+                    canvas,
+                    position,
+                    cell_count,
+                    &run[0].style,
+                    blend_mode,
+                );
+            }
+        }
+    }
+
+    for row in 0..grid.height {
+        if let Some(cells) = grid.row(row) {
+            for (column, run) in style_runs(cells) {
+                let position = cell_position(column, row);
+                let text: Vec<String> = run.iter().map(|cell| cell.text.clone()).collect();
+                grid_render.draw_foreground(canvas, position, &text, &run[0].style);
+                grid_render.draw_decorations(canvas, position, run.len() as u64, &run[0].style);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_window() -> RenderedWindow {
+        let mut window = RenderedWindow::new(2);
+        window.handle_window_draw_command(WindowDrawCommand::Position {
+            grid_position: (0.0, 0.0),
+            width: 10,
+            height: 10,
+            floating_order: None,
+            external: false,
+            anchored: false,
+            separator: None,
+        });
+        window
+    }
+
+    fn scroll_batch(window: &mut RenderedWindow, rows: i64, scroll_delta: Option<f64>) {
+        window.handle_window_draw_command(WindowDrawCommand::Scroll {
+            top: 0,
+            bottom: 10,
+            left: 0,
+            right: 10,
+            rows,
+            columns: 0,
+        });
+        if let Some(scroll_delta) = scroll_delta {
+            window.handle_window_draw_command(WindowDrawCommand::Viewport { scroll_delta });
+        }
+        window.finish_batch();
+    }
+
+    #[test]
+    fn scrolls_without_a_viewport_change_do_not_animate() {
+        let mut window = test_window();
+        scroll_batch(&mut window, 1, None);
+        assert!(window.scroll_animation.is_none());
+
+        // The snapshot of a redraw must not animate a viewport change in a later batch
+        window.handle_window_draw_command(WindowDrawCommand::Viewport { scroll_delta: 1.0 });
+        assert!(window.scroll_animation.is_none());
+
+        scroll_batch(&mut window, 1, Some(0.0));
+        assert!(window.scroll_animation.is_none());
+        assert_eq!(window.scroll_offset(), 0.0);
+    }
+
+    #[test]
+    fn scrolls_with_a_viewport_change_slide_into_place() {
+        let settings = RenderSettings::default();
+        let mut window = test_window();
+        scroll_batch(&mut window, 3, Some(3.0));
+        assert_eq!(window.scroll_offset(), 3.0);
+
+        window.animate(settings.scroll_animation_length, &settings);
+        assert!(window.scroll_animation.is_none());
+        assert_eq!(window.scroll_offset(), 0.0);
+    }
+
+    #[test]
+    fn scrolls_mid_animation_start_from_the_current_offset() {
+        let settings = RenderSettings::default();
+        let mut window = test_window();
+        scroll_batch(&mut window, 3, Some(3.0));
+        window.animate(settings.scroll_animation_length / 10.0, &settings);
+        let current_offset = window.scroll_offset();
+        assert!(current_offset > 0.0 && current_offset < 3.0);
+
+        scroll_batch(&mut window, 2, Some(2.0));
+        assert_eq!(window.scroll_offset(), current_offset + 2.0);
+
+        scroll_batch(&mut window, -4, Some(-4.0));
+        assert_eq!(window.scroll_offset(), current_offset - 2.0);
+    }
+
+    #[test]
+    fn scroll_offsets_stay_within_the_grid() {
+        let mut window = test_window();
+        scroll_batch(&mut window, 8, Some(8.0));
+        scroll_batch(&mut window, 8, Some(8.0));
+        assert_eq!(window.scroll_offset(), 10.0);
+    }
+}
@@ -0,0 +1,87 @@
+use skia_safe::{Color, Color4f};
+
+use crate::settings::*;
+
+#[derive(Clone, SettingGroup)]
+pub struct RenderSettings {
+    /// Seconds a scrolled window takes to slide into place, 0 jumps there instantly
+    pub scroll_animation_length: f32,
+    /// Seconds a moved or resized window takes to reach its new place, 0 disables it
+    pub position_animation_length: f32,
+    /// Draws a drop shadow under anchored floats
+    pub floating_shadow: bool,
+    /// Degrees clockwise from the x axis the shadow is cast towards
+    pub floating_shadow_angle: f32,
+    /// How far the shadow spreads, in pixels
+    pub floating_light_radius: f32,
+    /// `#rrggbb` or `#rrggbbaa`
+    pub floating_shadow_color: String,
+    /// Pixels the corners of anchored floats are rounded by
+    pub floating_corner_radius: f32,
+    /// Blur sigma applied to the content behind anchored floats, 0 disables it
+    pub floating_blur_amount: f32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            scroll_animation_length: 0.3,
+            position_animation_length: 0.15,
+            floating_shadow: false,
+            floating_shadow_angle: 45.0,
+            floating_light_radius: 10.0,
+            floating_shadow_color: "#00000080".to_owned(),
+            floating_corner_radius: 0.0,
+            floating_blur_amount: 0.0,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Falls back to translucent black when the setting is not a valid hex color
+    pub fn shadow_color(&self) -> Color4f {
+        let hex = self.floating_shadow_color.trim_start_matches('#');
+        let value = u32::from_str_radix(hex, 16).ok();
+        match (hex.len(), value) {
+            (6, Some(rgb)) => Color4f::from(Color::new(0xff00_0000 | rgb)),
+            (8, Some(rgba)) => Color4f::from(Color::new(rgba.rotate_right(8))),
+            _ => Color4f::new(0.0, 0.0, 0.0, 0.5),
+        }
+    }
+}
+
+#[derive(Clone, SettingGroup)]
+#[setting_prefix = "cursor"]
+pub struct CursorSettings {
+    /// Seconds the cursor takes to reach a new cell, 0 jumps there instantly
+    pub animation_length: f32,
+    /// How far the trailing corners lag behind on long jumps, from 0 (no trail) to 1
+    pub trail_size: f32,
+    /// One of `linear`, `quad`, `cubic` or `expo`
+    pub animation_easing: String,
+    /// Insert mode effect: `railgun`, `torpedo`, `pixiedust`, `sonicboom`, `ripple` or empty
+    pub vfx_mode: String,
+    /// From 0 (invisible) to 255 (opaque)
+    pub vfx_opacity: f32,
+    /// Seconds a particle or highlight lives
+    pub vfx_particle_lifetime: f32,
+    /// Particles emitted per cell travelled
+    pub vfx_particle_density: f32,
+    /// Lines per second particles fly at
+    pub vfx_particle_speed: f32,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        Self {
+            animation_length: 0.0,
+            trail_size: 0.7,
+            animation_easing: "expo".to_owned(),
+            vfx_mode: String::new(),
+            vfx_opacity: 200.0,
+            vfx_particle_lifetime: 1.2,
+            vfx_particle_density: 7.0,
+            vfx_particle_speed: 10.0,
+        }
+    }
+}
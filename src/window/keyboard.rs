@@ -0,0 +1,157 @@
+use glutin::event::{ModifiersState, VirtualKeyCode};
+
+fn key_name(keycode: VirtualKeyCode) -> Option<&'static str> {
+    use VirtualKeyCode::*;
+    Some(match keycode {
+        A => "a",
+        B => "b",
+        C => "c",
+        D => "d",
+        E => "e",
+        F => "f",
+        G => "g",
+        H => "h",
+        I => "i",
+        J => "j",
+        K => "k",
+        L => "l",
+        M => "m",
+        N => "n",
+        O => "o",
+        P => "p",
+        Q => "q",
+        R => "r",
+        S => "s",
+        T => "t",
+        U => "u",
+        V => "v",
+        W => "w",
+        X => "x",
+        Y => "y",
+        Z => "z",
+        Insert => "Insert",
+        _ => return None,
+    })
+}
+
+fn special_key_name(keycode: VirtualKeyCode) -> Option<&'static str> {
+    use VirtualKeyCode::*;
+    Some(match keycode {
+        Up => "Up",
+        Down => "Down",
+        Left => "Left",
+        Right => "Right",
+        Home => "Home",
+        End => "End",
+        PageUp => "PageUp",
+        PageDown => "PageDown",
+        Insert => "Insert",
+        Delete => "Del",
+        F1 => "F1",
+        F2 => "F2",
+        F3 => "F3",
+        F4 => "F4",
+        F5 => "F5",
+        F6 => "F6",
+        F7 => "F7",
+        F8 => "F8",
+        F9 => "F9",
+        F10 => "F10",
+        F11 => "F11",
+        F12 => "F12",
+        _ => return None,
+    })
+}
+
+fn modifier_prefix(modifiers: ModifiersState, use_shift: bool, use_logo: bool) -> String {
+    let mut prefix = String::new();
+    if modifiers.ctrl() {
+        prefix.push_str("C-");
+    }
+    if modifiers.alt() {
+        prefix.push_str("M-");
+    }
+    if modifiers.shift() && use_shift {
+        prefix.push_str("S-");
+    }
+    if modifiers.logo() && use_logo {
+        prefix.push_str("D-");
+    }
+    prefix
+}
+
+fn format_input(name: &str, modifiers: ModifiersState, special: bool, use_logo: bool) -> String {
+    let prefix = modifier_prefix(modifiers, special, use_logo);
+    if prefix.is_empty() && name.chars().count() == 1 {
+        name.to_owned()
+    } else {
+        format!("<{}{}>", prefix, name)
+    }
+}
+
+/// Translates keys which do not produce a character into neovim input
+pub fn keycode_to_input(
+    keycode: VirtualKeyCode,
+    modifiers: ModifiersState,
+    use_logo: bool,
+) -> Option<String> {
+    special_key_name(keycode).map(|name| format_input(name, modifiers, true, use_logo))
+}
+
+/// Translates a received character into neovim input
+pub fn character_to_input(
+    character: char,
+    modifiers: ModifiersState,
+    use_logo: bool,
+) -> Option<String> {
+    let (name, special) = match character {
+        '\r' | '\n' => ("CR".to_owned(), true),
+        '\t' => ("Tab".to_owned(), true),
+        '\u{8}' => ("BS".to_owned(), true),
+        '\u{7f}' if cfg!(target_os = "macos") => ("BS".to_owned(), true),
+        '\u{1b}' => ("Esc".to_owned(), true),
+        ' ' => ("Space".to_owned(), true),
+        '<' => ("lt".to_owned(), false),
+        '\u{1}'..='\u{1a}' => (((character as u8 - 1 + b'a') as char).to_string(), false),
+        _ if character.is_control() => return None,
+        _ => (character.to_string(), false),
+    };
+    Some(format_input(&name, modifiers, special, use_logo))
+}
+
+/// The modifiers of a mouse event in the form nvim_input_mouse expects
+pub fn mouse_modifiers(modifiers: ModifiersState, use_logo: bool) -> String {
+    modifier_prefix(modifiers, true, use_logo)
+}
+
+/// Checks a vim style shortcut such as `<C-S-v>` against a key press
+pub fn shortcut_matches(
+    shortcut: &str,
+    modifiers: ModifiersState,
+    keycode: VirtualKeyCode,
+) -> bool {
+    let shortcut = match shortcut
+        .strip_prefix('<')
+        .and_then(|shortcut| shortcut.strip_suffix('>'))
+    {
+        Some(shortcut) => shortcut,
+        None => return false,
+    };
+    let mut parts = shortcut.rsplitn(2, '-');
+    let key = parts.next().unwrap_or_default();
+    let mut expected = ModifiersState::empty();
+    for modifier in parts.next().unwrap_or_default().split('-') {
+        match modifier.to_ascii_uppercase().as_str() {
+            "" => {}
+            "C" => expected |= ModifiersState::CTRL,
+            "S" => expected |= ModifiersState::SHIFT,
+            "A" | "M" => expected |= ModifiersState::ALT,
+            "D" => expected |= ModifiersState::LOGO,
+            _ => return false,
+        }
+    }
+    match key_name(keycode) {
+        Some(name) => name.eq_ignore_ascii_case(key) && modifiers == expected,
+        None => false,
+    }
+}
@@ -1,4 +1,5 @@
 mod dimensions;
+mod keyboard;
 mod settings;
 mod window_wrapper;
 
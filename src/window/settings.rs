@@ -8,6 +8,11 @@ pub struct WindowSettings {
     pub transparency: f32,
     pub fullscreen: bool,
     pub remember_window_size: bool,
+    pub padding_top: u32,
+    pub padding_left: u32,
+    pub padding_right: u32,
+    pub padding_bottom: u32,
+    pub busy_spinner: bool,
 }
 
 impl Default for WindowSettings {
@@ -21,12 +26,43 @@ impl Default for WindowSettings {
             transparency: 1.0,
             fullscreen: false,
             remember_window_size: false,
+            padding_top: 0,
+            padding_left: 0,
+            padding_right: 0,
+            padding_bottom: 0,
+            busy_spinner: false,
         }
     }
 }
 
-#[derive(Clone, Default, SettingGroup)]
+impl WindowSettings {
+    /// The padding as (top, left, right, bottom)
+    pub fn padding(&self) -> (u32, u32, u32, u32) {
+        (
+            self.padding_top,
+            self.padding_left,
+            self.padding_right,
+            self.padding_bottom,
+        )
+    }
+}
+
+#[derive(Clone, SettingGroup)]
 #[setting_prefix = "input"]
 pub struct KeyboardSettings {
     pub use_logo: bool,
+    pub paste_shortcut: String,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            use_logo: false,
+            paste_shortcut: if cfg!(target_os = "macos") {
+                "<D-v>".to_owned()
+            } else {
+                "<C-S-v>".to_owned()
+            },
+        }
+    }
 }
@@ -0,0 +1,55 @@
+use glutin::{dpi::PhysicalSize, event_loop::EventLoopWindowTarget, window::WindowBuilder};
+
+use crate::window::{Dimensions, WindowSettings};
+
+use super::{gl_window::GlWindow, resize_controller::ResizeController};
+
+/// A grid which neovim asked to show outside of the main window
+pub struct ExternalWindow {
+    pub grid_id: u64,
+    /// The neovim window handle, used to focus or close the window
+    pub window: u64,
+    pub gl_window: GlWindow,
+    pub resize_controller: ResizeController,
+    /// The grid cell under the mouse
+    pub mouse_position: (u64, u64),
+}
+
+impl ExternalWindow {
+    pub fn new(
+        grid_id: u64,
+        window: u64,
+        window_size: PhysicalSize<u32>,
+        title: &str,
+        window_target: &EventLoopWindowTarget<()>,
+    ) -> Self {
+        let window_builder = WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(window_size)
+            .with_transparent(true);
+        ExternalWindow {
+            grid_id,
+            window,
+            gl_window: GlWindow::new(window_builder, window_target),
+            resize_controller: ResizeController::new(),
+            mouse_position: (0, 0),
+        }
+    }
+
+    /// The window size that fits a grid of `grid_size` cells
+    pub fn window_size_for(
+        grid_size: Dimensions,
+        font_dimensions: Dimensions,
+        settings: &WindowSettings,
+    ) -> PhysicalSize<u32> {
+        let padding = Dimensions {
+            width: (settings.padding_left + settings.padding_right) as u64,
+            height: (settings.padding_top + settings.padding_bottom) as u64,
+        };
+        let content_size = grid_size * font_dimensions;
+        PhysicalSize::from(Dimensions {
+            width: content_size.width + padding.width,
+            height: content_size.height + padding.height,
+        })
+    }
+}
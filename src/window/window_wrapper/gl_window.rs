@@ -0,0 +1,81 @@
+use glutin::{
+    self,
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder},
+    ContextBuilder, PossiblyCurrent, WindowedContext,
+};
+use log::error;
+use skia_safe::Canvas;
+
+use super::renderer::SkiaRenderer;
+
+/// An OS window with its own gl context and skia surface. Only one context is current at a
+/// time, so every window has to be made current before drawing to it.
+pub struct GlWindow {
+    // Only None while the context is being made current
+    windowed_context: Option<WindowedContext<PossiblyCurrent>>,
+    skia_renderer: SkiaRenderer,
+}
+
+impl GlWindow {
+    pub fn new(window_builder: WindowBuilder, window_target: &EventLoopWindowTarget<()>) -> Self {
+        let windowed_context = ContextBuilder::new()
+            .with_pixel_format(24, 8)
+            .with_stencil_buffer(8)
+            .with_gl_profile(glutin::GlProfile::Core)
+            .with_vsync(false)
+            .with_srgb(false)
+            .build_windowed(window_builder, window_target)
+            .unwrap();
+        let windowed_context = unsafe { windowed_context.make_current().unwrap() };
+        let skia_renderer = SkiaRenderer::new(&windowed_context);
+        GlWindow {
+            windowed_context: Some(windowed_context),
+            skia_renderer,
+        }
+    }
+
+    fn windowed_context(&self) -> &WindowedContext<PossiblyCurrent> {
+        self.windowed_context
+            .as_ref()
+            .expect("Window context is missing")
+    }
+
+    pub fn window(&self) -> &Window {
+        self.windowed_context().window()
+    }
+
+    pub fn make_current(&mut self) {
+        let windowed_context = match self.windowed_context.take() {
+            Some(windowed_context) if windowed_context.is_current() => windowed_context,
+            Some(windowed_context) => match unsafe { windowed_context.make_current() } {
+                Ok(windowed_context) => windowed_context,
+                Err((windowed_context, error)) => {
+                    error!("Could not make window context current: {}", error);
+                    windowed_context
+                }
+            },
+            None => return,
+        };
+        self.windowed_context = Some(windowed_context);
+    }
+
+    pub fn canvas(&mut self) -> &mut Canvas {
+        self.skia_renderer.canvas()
+    }
+
+    /// Recreates the surface for the new window size. The context has to be current.
+    pub fn resize(&mut self) {
+        if let Some(windowed_context) = &self.windowed_context {
+            self.skia_renderer.resize(windowed_context);
+        }
+    }
+
+    /// Flushes the drawing commands and shows the frame. The context has to be current.
+    pub fn present(&mut self) {
+        self.skia_renderer.gr_context.flush(None);
+        if let Err(error) = self.windowed_context().swap_buffers() {
+            error!("Could not swap buffers: {}", error);
+        }
+    }
+}
@@ -1,45 +1,76 @@
+mod external_window;
+mod gl_window;
 mod renderer;
+mod resize_controller;
 
 use std::{
+    collections::HashMap,
     sync::{atomic::AtomicBool, mpsc::Receiver, Arc},
     time::{Duration, Instant},
 };
 
 use crate::settings::SETTINGS;
 use crate::{
-    bridge::UiCommand,
+    bridge::{UiCommand, PASTE_PROGRESS},
     cmd_line::CmdLineSettings,
     editor::{DrawCommand, WindowCommand},
     logging_sender::LoggingUnboundedSender,
     render::Render,
-    window::WindowSettings,
+    window::{
+        keyboard::{character_to_input, keycode_to_input, mouse_modifiers, shortcut_matches},
+        Dimensions, KeyboardSettings, WindowSettings,
+    },
 };
 
 use glutin::{
     self,
-    event::Event,
-    event_loop::{ControlFlow, EventLoop},
-    window::{self, Icon},
-    ContextBuilder, WindowedContext,
+    dpi::PhysicalPosition,
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::{self, CursorIcon, Icon, WindowId},
 };
 use image::{load_from_memory, GenericImageView, Pixel};
 use log::trace;
+use tokio::sync::oneshot;
 
-use self::renderer::SkiaRenderer;
+use self::{
+    external_window::ExternalWindow, gl_window::GlWindow, resize_controller::ResizeController,
+};
 
 static ICON: &[u8] = include_bytes!("../../../assets/xvim.ico");
 
+// Short busy periods are normal, only slow ones are worth pointing out
+const BUSY_INDICATOR_DELAY: Duration = Duration::from_millis(500);
+// Closing the window again within this period quits without saving
+const FORCE_QUIT_PERIOD: Duration = Duration::from_secs(3);
+
 pub struct GlutinWindowWrapper {
-    windowed_context: WindowedContext<glutin::PossiblyCurrent>,
-    skia_renderer: SkiaRenderer,
+    main_window: GlWindow,
+    external_windows: HashMap<u64, ExternalWindow>,
     render: Render,
     ui_command_sender: LoggingUnboundedSender<UiCommand>,
     window_command_receiver: Receiver<WindowCommand>,
     title: String,
+    displayed_title: String,
+    modifiers: ModifiersState,
+    ignore_next_character: bool,
+    resize_controller: ResizeController,
+    /// The padding when the grid sizes were last requested
+    padding: (u32, u32, u32, u32),
+    busy_since: Option<Instant>,
+    wait_cursor_shown: bool,
+    close_requested_at: Option<Instant>,
+    mouse_position: (u64, u64),
+    pressed_mouse_button: Option<&'static str>,
+    // Touchpads scroll by pixels, which are sent to neovim a line at a time
+    scroll_pixels: f64,
 }
 
 impl GlutinWindowWrapper {
-    fn handle_window_commands(&mut self) {
+    fn handle_window_commands(&mut self, window_target: &EventLoopWindowTarget<()>) {
         let window_commands = self.window_command_receiver.try_iter().collect::<Vec<_>>();
         for command in window_commands {
             match command {
@@ -47,32 +78,398 @@ impl GlutinWindowWrapper {
                     self.handle_title_changed(new_title);
                 }
                 WindowCommand::SetMouseEnable(_) => todo!(),
+                WindowCommand::SetBusy(busy) => {
+                    self.busy_since = if busy {
+                        self.busy_since.or_else(|| Some(Instant::now()))
+                    } else {
+                        None
+                    };
+                }
+                WindowCommand::ShowExternalWindow {
+                    grid_id,
+                    window,
+                    width,
+                    height,
+                } => {
+                    let grid_size = Dimensions { width, height };
+                    self.show_external_window(grid_id, window, grid_size, window_target);
+                }
+                WindowCommand::CloseExternalWindow(grid_id) => {
+                    self.close_external_window(grid_id);
+                }
             }
         }
     }
 
-    fn draw_frame(&mut self, _dt: f32) {
-        let window = self.windowed_context.window();
+    fn show_external_window(
+        &mut self,
+        grid_id: u64,
+        window: u64,
+        grid_size: Dimensions,
+        window_target: &EventLoopWindowTarget<()>,
+    ) {
+        let window_size = ExternalWindow::window_size_for(
+            grid_size,
+            self.render.font_dimensions(),
+            &SETTINGS.get::<WindowSettings>(),
+        );
+        let title = &self.title;
+        let external_window = self.external_windows.entry(grid_id).or_insert_with(|| {
+            ExternalWindow::new(grid_id, window, window_size, title, window_target)
+        });
+        external_window.window = window;
+        if external_window.gl_window.window().inner_size() != window_size {
+            external_window
+                .gl_window
+                .window()
+                .set_inner_size(window_size);
+        }
+        external_window.resize_controller.attached(grid_size);
+    }
+
+    fn handle_close_requested(&mut self) {
+        let now = Instant::now();
+        let repeated = self
+            .close_requested_at
+            .map_or(false, |requested_at| now - requested_at < FORCE_QUIT_PERIOD);
+        if repeated {
+            self.ui_command_sender.send(UiCommand::ForceQuit).ok();
+        } else {
+            self.ui_command_sender.send(UiCommand::Quit).ok();
+        }
+        self.close_requested_at = Some(now);
+    }
+
+    fn close_external_window(&mut self, grid_id: u64) {
+        if let Some(mut external_window) = self.external_windows.remove(&grid_id) {
+            // Skia releases its gpu resources through whichever context is current
+            external_window.gl_window.make_current();
+            drop(external_window);
+            self.main_window.make_current();
+        }
+    }
+
+    fn external_grid_id(&self, window_id: WindowId) -> Option<u64> {
+        self.external_windows
+            .values()
+            .find(|external_window| external_window.gl_window.window().id() == window_id)
+            .map(|external_window| external_window.grid_id)
+    }
+
+    /// How long neovim has been busy, once that is long enough to show it
+    fn long_busy_duration(&self) -> Option<Duration> {
+        self.busy_since
+            .map(|busy_since| busy_since.elapsed())
+            .filter(|busy_duration| *busy_duration >= BUSY_INDICATOR_DELAY)
+    }
+
+    fn update_busy_cursor(&mut self) {
+        let show_wait_cursor = self.long_busy_duration().is_some();
+        if show_wait_cursor != self.wait_cursor_shown {
+            let cursor_icon = if show_wait_cursor {
+                CursorIcon::Wait
+            } else {
+                CursorIcon::Default
+            };
+            self.main_window.window().set_cursor_icon(cursor_icon);
+            self.wait_cursor_shown = show_wait_cursor;
+        }
+    }
+
+    fn draw_frame(&mut self, dt: f32) {
+        self.main_window.make_current();
+        let font_changed = self.render.draw_frame(self.main_window.canvas(), dt);
+        if let Some(busy_duration) = self.long_busy_duration() {
+            if SETTINGS.get::<WindowSettings>().busy_spinner {
+                self.render
+                    .draw_busy_spinner(self.main_window.canvas(), busy_duration);
+            }
+        }
+        self.main_window.present();
+        for external_window in self.external_windows.values_mut() {
+            external_window.gl_window.make_current();
+            self.render
+                .draw_external_window(external_window.grid_id, external_window.gl_window.canvas());
+            external_window.gl_window.present();
+        }
+        self.main_window.make_current();
+        if font_changed {
+            self.request_grid_resize();
+        }
+    }
+
+    fn current_grid_size(&self) -> Dimensions {
+        ResizeController::grid_size_for(
+            self.main_window.window().inner_size(),
+            self.render.font_dimensions(),
+            &SETTINGS.get::<WindowSettings>(),
+        )
+    }
+
+    fn request_grid_resize(&mut self) {
+        let grid_size = self.current_grid_size();
+        self.resize_controller.request(grid_size);
+    }
+
+    /// Padding changed at runtime leaves room for a different number of cells
+    fn handle_padding_changed(&mut self) {
+        let settings = SETTINGS.get::<WindowSettings>();
+        let padding = settings.padding();
+        if padding == self.padding {
+            return;
+        }
+        self.padding = padding;
+        self.request_grid_resize();
+        let font_dimensions = self.render.font_dimensions();
+        for external_window in self.external_windows.values_mut() {
+            let grid_size = ResizeController::grid_size_for(
+                external_window.gl_window.window().inner_size(),
+                font_dimensions,
+                &settings,
+            );
+            external_window.resize_controller.request(grid_size);
+        }
+    }
+
+    fn send_pending_resize(&mut self) {
+        if let Some(grid_size) = self.resize_controller.poll() {
+            self.ui_command_sender
+                .send(UiCommand::Resize {
+                    width: grid_size.width,
+                    height: grid_size.height,
+                })
+                .ok();
+        }
+        for external_window in self.external_windows.values_mut() {
+            if let Some(grid_size) = external_window.resize_controller.poll() {
+                self.ui_command_sender
+                    .send(UiCommand::ResizeGrid {
+                        grid: external_window.grid_id,
+                        width: grid_size.width,
+                        height: grid_size.height,
+                    })
+                    .ok();
+            }
+        }
     }
 
     fn handle_title_changed(&mut self, new_title: String) {
         self.title = new_title;
-        self.windowed_context.window().set_title(&self.title);
+        self.refresh_title();
+    }
+
+    fn refresh_title(&mut self) {
+        let title = match PASTE_PROGRESS.percentage() {
+            Some(percentage) => format!("{} (pasting {}%, Esc to cancel)", self.title, percentage),
+            None => self.title.clone(),
+        };
+        if title != self.displayed_title {
+            self.main_window.window().set_title(&title);
+            self.displayed_title = title;
+        }
+    }
+
+    fn handle_keyboard_input(&mut self, input: KeyboardInput) {
+        if input.state != ElementState::Pressed {
+            return;
+        }
+        // Only the character produced by a consumed shortcut should be dropped
+        self.ignore_next_character = false;
+        let keycode = match input.virtual_keycode {
+            Some(keycode) => keycode,
+            None => return,
+        };
+        if keycode == VirtualKeyCode::Escape && PASTE_PROGRESS.is_active() {
+            PASTE_PROGRESS.request_cancel();
+            self.ignore_next_character = true;
+            return;
+        }
+        let settings = SETTINGS.get::<KeyboardSettings>();
+        if shortcut_matches(&settings.paste_shortcut, self.modifiers, keycode) {
+            self.ui_command_sender.send(UiCommand::Paste).ok();
+            self.ignore_next_character = true;
+            return;
+        }
+        if let Some(input) = keycode_to_input(keycode, self.modifiers, settings.use_logo) {
+            self.ui_command_sender.send(UiCommand::Keyboard(input)).ok();
+        }
+    }
+
+    fn handle_received_character(&mut self, character: char) {
+        if std::mem::take(&mut self.ignore_next_character) {
+            return;
+        }
+        let use_logo = SETTINGS.get::<KeyboardSettings>().use_logo;
+        if let Some(input) = character_to_input(character, self.modifiers, use_logo) {
+            self.ui_command_sender.send(UiCommand::Keyboard(input)).ok();
+        }
+    }
+
+    /// The cell under the mouse in the main window, or in the external window of `grid`
+    fn mouse_position(&self, grid: Option<u64>) -> (u64, u64) {
+        match grid {
+            Some(grid_id) => self
+                .external_windows
+                .get(&grid_id)
+                .map_or((0, 0), |external_window| external_window.mouse_position),
+            None => self.mouse_position,
+        }
+    }
+
+    fn send_mouse_command(&self, grid: Option<u64>, button: &str, action: &str) {
+        let use_logo = SETTINGS.get::<KeyboardSettings>().use_logo;
+        self.ui_command_sender
+            .send(UiCommand::Mouse {
+                button: button.to_owned(),
+                action: action.to_owned(),
+                modifiers: mouse_modifiers(self.modifiers, use_logo),
+                grid,
+                position: self.mouse_position(grid),
+            })
+            .ok();
+    }
+
+    /// Tracks the cell under the mouse, dragging while a button is held
+    fn handle_cursor_moved(&mut self, grid: Option<u64>, position: PhysicalPosition<f64>) {
+        let settings = SETTINGS.get::<WindowSettings>();
+        let font_dimensions = self.render.font_dimensions();
+        let mouse_position = (
+            ((position.x - settings.padding_left as f64).max(0.0)
+                / font_dimensions.width.max(1) as f64) as u64,
+            ((position.y - settings.padding_top as f64).max(0.0)
+                / font_dimensions.height.max(1) as f64) as u64,
+        );
+        if mouse_position == self.mouse_position(grid) {
+            return;
+        }
+        match grid {
+            Some(grid_id) => {
+                if let Some(external_window) = self.external_windows.get_mut(&grid_id) {
+                    external_window.mouse_position = mouse_position;
+                }
+            }
+            None => self.mouse_position = mouse_position,
+        }
+        if let Some(button) = self.pressed_mouse_button {
+            self.send_mouse_command(grid, button, "drag");
+        }
+    }
+
+    fn handle_mouse_input(&mut self, grid: Option<u64>, state: ElementState, button: MouseButton) {
+        let button = match button {
+            MouseButton::Left => "left",
+            MouseButton::Right => "right",
+            MouseButton::Middle => "middle",
+            MouseButton::Other(_) => return,
+        };
+        match state {
+            ElementState::Pressed => {
+                self.pressed_mouse_button = Some(button);
+                self.send_mouse_command(grid, button, "press");
+            }
+            ElementState::Released => {
+                self.pressed_mouse_button = None;
+                self.send_mouse_command(grid, button, "release");
+            }
+        }
+    }
+
+    fn handle_mouse_wheel(&mut self, grid: Option<u64>, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines as f64,
+            MouseScrollDelta::PixelDelta(position) => {
+                self.scroll_pixels += position.y;
+                let line_height = self.render.font_dimensions().height.max(1) as f64;
+                let lines = (self.scroll_pixels / line_height).trunc();
+                self.scroll_pixels -= lines * line_height;
+                lines
+            }
+        };
+        let action = if lines > 0.0 { "up" } else { "down" };
+        for _ in 0..lines.abs().round() as u64 {
+            self.send_mouse_command(grid, "wheel", action);
+        }
+    }
+
+    /// External windows handle their own size, focus, close requests and mouse input, which
+    /// goes to their grid. Keyboard input goes to neovim no matter which window received it.
+    fn handle_external_window_event(&mut self, grid_id: u64, event: WindowEvent) {
+        let font_dimensions = self.render.font_dimensions();
+        let external_window = match self.external_windows.get_mut(&grid_id) {
+            Some(external_window) => external_window,
+            None => return,
+        };
+        match event {
+            WindowEvent::Resized(window_size) => {
+                external_window.gl_window.make_current();
+                external_window.gl_window.resize();
+                let grid_size = ResizeController::grid_size_for(
+                    window_size,
+                    font_dimensions,
+                    &SETTINGS.get::<WindowSettings>(),
+                );
+                external_window.resize_controller.request(grid_size);
+            }
+            WindowEvent::CloseRequested => {
+                self.ui_command_sender
+                    .send(UiCommand::CloseWindow(external_window.window))
+                    .ok();
+            }
+            WindowEvent::Focused(focused) => {
+                self.render.cursor_renderer.focused = focused;
+                if focused {
+                    self.ui_command_sender
+                        .send(UiCommand::FocusWindow(external_window.window))
+                        .ok();
+                }
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                self.handle_received_character(character);
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                self.handle_keyboard_input(input);
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.handle_cursor_moved(Some(grid_id), position);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.handle_mouse_wheel(Some(grid_id), delta);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.handle_mouse_input(Some(grid_id), state, button);
+            }
+            _ => {}
+        }
     }
 
     fn handle_event(&mut self, event: Event<()>, _running: &Arc<AtomicBool>) {
         match event {
             Event::NewEvents(_) => {}
+            // Closed external windows still report their destruction, which must not quit
+            Event::WindowEvent { window_id, event }
+                if window_id != self.main_window.window().id() =>
+            {
+                if let Some(grid_id) = self.external_grid_id(window_id) {
+                    self.handle_external_window_event(grid_id, event);
+                }
+            }
             Event::WindowEvent { event, .. } => match event {
                 glutin::event::WindowEvent::Resized(_) => {
-                    trace!("unhandled Resized event");
+                    self.main_window.make_current();
+                    self.main_window.resize();
+                    self.request_grid_resize();
                 }
                 glutin::event::WindowEvent::Moved(_) => {
                     trace!("unhanded Moved event");
                 }
-                glutin::event::WindowEvent::CloseRequested
-                | glutin::event::WindowEvent::Destroyed => {
-                    self.ui_command_sender.send(UiCommand::Quit).ok().unwrap();
+                glutin::event::WindowEvent::CloseRequested => {
+                    self.handle_close_requested();
+                }
+                glutin::event::WindowEvent::Destroyed => {
+                    self.ui_command_sender.send(UiCommand::Quit).ok();
                 }
 
                 glutin::event::WindowEvent::DroppedFile(_) => {
@@ -84,50 +481,35 @@ impl GlutinWindowWrapper {
                 glutin::event::WindowEvent::HoveredFileCancelled => {
                     trace!("unhandled HoveredFileCancelled event");
                 }
-                glutin::event::WindowEvent::ReceivedCharacter(_) => {
-                    trace!("undhandled ReceivedCharacter event");
+                glutin::event::WindowEvent::ReceivedCharacter(character) => {
+                    self.handle_received_character(character);
                 }
-                glutin::event::WindowEvent::Focused(_) => {
-                    trace!("unhandled Focus event");
+                glutin::event::WindowEvent::Focused(focused) => {
+                    self.render.cursor_renderer.focused = focused;
+                    if focused && !self.external_windows.is_empty() {
+                        self.ui_command_sender.send(UiCommand::FocusMainWindow).ok();
+                    }
                 }
-                glutin::event::WindowEvent::KeyboardInput {
-                    device_id,
-                    input,
-                    is_synthetic,
-                } => {
-                    trace!("unhandled keyboard input");
+                glutin::event::WindowEvent::KeyboardInput { input, .. } => {
+                    self.handle_keyboard_input(input);
                 }
-                glutin::event::WindowEvent::ModifiersChanged(_) => {
-                    trace!("unhandled ModifiersChanged event");
+                glutin::event::WindowEvent::ModifiersChanged(modifiers) => {
+                    self.modifiers = modifiers;
                 }
-                glutin::event::WindowEvent::CursorMoved {
-                    device_id,
-                    position,
-                    modifiers,
-                } => {
-                    trace!("unhandled cursormove event");
+                glutin::event::WindowEvent::CursorMoved { position, .. } => {
+                    self.handle_cursor_moved(None, position);
                 }
-                glutin::event::WindowEvent::CursorEntered { device_id } => {
+                glutin::event::WindowEvent::CursorEntered { .. } => {
                     trace!("unhandled cursorenter event");
                 }
-                glutin::event::WindowEvent::CursorLeft { device_id } => {
+                glutin::event::WindowEvent::CursorLeft { .. } => {
                     trace!("unhandled cursorleft event");
                 }
-                glutin::event::WindowEvent::MouseWheel {
-                    device_id,
-                    delta,
-                    phase,
-                    modifiers,
-                } => {
-                    trace!("unhandled mouseWheel event");
+                glutin::event::WindowEvent::MouseWheel { delta, .. } => {
+                    self.handle_mouse_wheel(None, delta);
                 }
-                glutin::event::WindowEvent::MouseInput {
-                    device_id,
-                    state,
-                    button,
-                    modifiers,
-                } => {
-                    trace!("unhandled mouseInput event");
+                glutin::event::WindowEvent::MouseInput { state, button, .. } => {
+                    self.handle_mouse_input(None, state, button);
                 }
                 glutin::event::WindowEvent::TouchpadPressure {
                     device_id,
@@ -146,11 +528,9 @@ impl GlutinWindowWrapper {
                 glutin::event::WindowEvent::Touch(_) => {
                     trace!("unhandled touch");
                 }
-                glutin::event::WindowEvent::ScaleFactorChanged {
-                    scale_factor,
-                    new_inner_size,
-                } => {
-                    trace!("unhandled scaleFactorChanged");
+                glutin::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    self.render.handle_scale_factor_update(scale_factor);
+                    self.request_grid_resize();
                 }
                 glutin::event::WindowEvent::ThemeChanged(_) => {
                     trace!("unhandled theme changed");
@@ -191,6 +571,7 @@ pub fn create_window(
     batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
     window_command_receiver: Receiver<WindowCommand>,
     ui_command_sender: LoggingUnboundedSender<UiCommand>,
+    grid_size_sender: oneshot::Sender<Dimensions>,
     running: Arc<AtomicBool>,
 ) {
     let icon = {
@@ -204,57 +585,76 @@ pub fn create_window(
     };
     let event_loop = EventLoop::new();
     let title = "Xvim".to_owned();
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
     let winit_window_builder = window::WindowBuilder::new()
         .with_title(&title)
+        .with_inner_size(glutin::dpi::PhysicalSize::<u32>::from(
+            cmd_line_settings.geometry,
+        ))
         .with_window_icon(Some(icon))
-        .with_maximized(SETTINGS.get::<CmdLineSettings>().maximized)
-        .with_decorations(!SETTINGS.get::<CmdLineSettings>().frameless);
-    let windowed_context = ContextBuilder::new()
-        .with_pixel_format(24, 8)
-        .with_stencil_buffer(8)
-        .with_gl_profile(glutin::GlProfile::Core)
-        .with_vsync(false)
-        .with_srgb(false)
-        .build_windowed(winit_window_builder, &event_loop)
-        .unwrap();
-    let windowed_context = unsafe { windowed_context.make_current().unwrap() };
-
-    let _window = windowed_context.window();
-
-    let scale_factor = windowed_context.window().scale_factor();
-    let render = Render::new(batched_draw_command_receiver, scale_factor);
+        .with_maximized(cmd_line_settings.maximized)
+        .with_decorations(!cmd_line_settings.frameless)
+        .with_transparent(true);
+    let main_window = GlWindow::new(winit_window_builder, &event_loop);
 
-    let skia_renderer = SkiaRenderer::new(&windowed_context);
+    let scale_factor = main_window.window().scale_factor();
+    let render = Render::new(batched_draw_command_receiver, scale_factor);
 
     log::info!("window created (scale_factor: {:.4})", scale_factor);
 
     let mut window_wrapper = GlutinWindowWrapper {
-        windowed_context,
-        skia_renderer,
+        main_window,
+        external_windows: HashMap::new(),
         render,
         ui_command_sender,
         window_command_receiver,
+        displayed_title: title.clone(),
         title,
+        modifiers: ModifiersState::empty(),
+        ignore_next_character: false,
+        resize_controller: ResizeController::new(),
+        padding: SETTINGS.get::<WindowSettings>().padding(),
+        busy_since: None,
+        wait_cursor_shown: false,
+        close_requested_at: None,
+        mouse_position: (0, 0),
+        pressed_mouse_button: None,
+        scroll_pixels: 0.0,
     };
 
+    let grid_size = window_wrapper.current_grid_size();
+    window_wrapper.resize_controller.attached(grid_size);
+    grid_size_sender.send(grid_size).ok();
+
     let mut previous_frame_start = Instant::now();
-    event_loop.run(move |e, _window_target, control_flow| {
+    event_loop.run(move |e, window_target, control_flow| {
         if !running.load(std::sync::atomic::Ordering::Relaxed) {
             std::process::exit(0);
         }
 
         let frame_start = Instant::now();
-        window_wrapper.handle_window_commands();
+        window_wrapper.handle_window_commands(window_target);
         window_wrapper.handle_event(e, &running);
+        window_wrapper.refresh_title();
+        window_wrapper.handle_padding_changed();
+        window_wrapper.send_pending_resize();
+        window_wrapper.update_busy_cursor();
 
         let refresh_rate = SETTINGS.get::<WindowSettings>().refresh_rate as f32;
         let expected_frame_length_seconds = 1.0 / refresh_rate;
         let frame_duration = Duration::from_secs_f32(expected_frame_length_seconds);
-        if frame_start - previous_frame_start > frame_duration {
+        let next_blink_transition = window_wrapper.render.next_blink_transition();
+        let blink_due = next_blink_transition.map_or(false, |transition| transition <= frame_start);
+        if blink_due || frame_start - previous_frame_start > frame_duration {
             let dt = previous_frame_start.elapsed().as_secs_f32();
             window_wrapper.draw_frame(dt);
             previous_frame_start = frame_start;
         }
-        *control_flow = ControlFlow::WaitUntil(previous_frame_start + frame_duration)
+        let next_frame = previous_frame_start + frame_duration;
+        let wake_up = window_wrapper
+            .render
+            .next_blink_transition()
+            .map_or(next_frame, |transition| transition.min(next_frame));
+        *control_flow = ControlFlow::WaitUntil(wake_up)
     });
 }
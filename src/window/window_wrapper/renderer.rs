@@ -1,7 +1,7 @@
 use gl::types::GLint;
 use skia_safe::{
     gpu::{gl::FramebufferInfo, BackendRenderTarget, DirectContext},
-    Surface,
+    Canvas, Surface,
 };
 use std::convert::TryInto;
 
@@ -40,6 +40,14 @@ impl SkiaRenderer {
             surface,
         }
     }
+
+    pub fn canvas(&mut self) -> &mut Canvas {
+        self.surface.canvas()
+    }
+
+    pub fn resize(&mut self, windowed_context: &WindowedContext) {
+        self.surface = create_surface(windowed_context, &mut self.gr_context, self.fb_info);
+    }
 }
 
 fn create_surface(
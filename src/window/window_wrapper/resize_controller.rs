@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use glutin::dpi::PhysicalSize;
+
+use crate::window::{Dimensions, WindowSettings};
+
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
+
+pub struct ResizeController {
+    grid_size: Option<Dimensions>,
+    pending: Option<(Dimensions, Instant)>,
+}
+
+impl ResizeController {
+    pub fn new() -> Self {
+        ResizeController {
+            grid_size: None,
+            pending: None,
+        }
+    }
+
+    pub fn grid_size_for(
+        window_size: PhysicalSize<u32>,
+        font_dimensions: Dimensions,
+        settings: &WindowSettings,
+    ) -> Dimensions {
+        let horizontal_padding = (settings.padding_left + settings.padding_right) as u64;
+        let vertical_padding = (settings.padding_top + settings.padding_bottom) as u64;
+        let content_size = Dimensions {
+            width: (window_size.width as u64).saturating_sub(horizontal_padding),
+            height: (window_size.height as u64).saturating_sub(vertical_padding),
+        };
+        let font_dimensions = Dimensions {
+            width: font_dimensions.width.max(1),
+            height: font_dimensions.height.max(1),
+        };
+        let grid_size = content_size / font_dimensions;
+        Dimensions {
+            width: grid_size.width.max(1),
+            height: grid_size.height.max(1),
+        }
+    }
+
+    /// Records the grid size that neovim was attached with
+    pub fn attached(&mut self, grid_size: Dimensions) {
+        self.grid_size = Some(grid_size);
+        self.pending = None;
+    }
+
+    /// Queues a grid size, restarting the debounce timer whenever the requested size changes
+    pub fn request(&mut self, grid_size: Dimensions) {
+        if self.grid_size == Some(grid_size) {
+            self.pending = None;
+        } else if self.pending.map(|(size, _)| size) != Some(grid_size) {
+            self.pending = Some((grid_size, Instant::now()));
+        }
+    }
+
+    /// Returns the grid size to send to neovim once the debounce period has elapsed
+    pub fn poll(&mut self) -> Option<Dimensions> {
+        match self.pending {
+            Some((grid_size, requested_at)) if requested_at.elapsed() >= RESIZE_DEBOUNCE => {
+                self.pending = None;
+                self.grid_size = Some(grid_size);
+                Some(grid_size)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    const FONT_DIMENSIONS: Dimensions = Dimensions {
+        width: 10,
+        height: 20,
+    };
+
+    fn settings((top, left, right, bottom): (u32, u32, u32, u32)) -> WindowSettings {
+        WindowSettings {
+            refresh_rate: 60,
+            no_idle: false,
+            transparency: 1.0,
+            fullscreen: false,
+            remember_window_size: false,
+            padding_top: top,
+            padding_left: left,
+            padding_right: right,
+            padding_bottom: bottom,
+            busy_spinner: false,
+        }
+    }
+
+    fn grid_size_for(width: u32, height: u32, padding: (u32, u32, u32, u32)) -> (u64, u64) {
+        ResizeController::grid_size_for(
+            PhysicalSize::new(width, height),
+            FONT_DIMENSIONS,
+            &settings(padding),
+        )
+        .into()
+    }
+
+    fn dimensions(width: u64, height: u64) -> Dimensions {
+        Dimensions { width, height }
+    }
+
+    #[test]
+    fn partial_cells_are_left_out() {
+        assert_eq!(grid_size_for(800, 480, (0, 0, 0, 0)), (80, 24));
+        assert_eq!(grid_size_for(809, 499, (0, 0, 0, 0)), (80, 24));
+    }
+
+    #[test]
+    fn padding_takes_room_from_the_grid() {
+        assert_eq!(grid_size_for(800, 480, (20, 10, 10, 20)), (78, 22));
+        assert_eq!(grid_size_for(800, 480, (5, 0, 0, 0)), (80, 23));
+    }
+
+    #[test]
+    fn grids_keep_at_least_one_cell() {
+        assert_eq!(grid_size_for(5, 5, (0, 0, 0, 0)), (1, 1));
+        assert_eq!(grid_size_for(100, 100, (100, 100, 100, 100)), (1, 1));
+        let zero_font = ResizeController::grid_size_for(
+            PhysicalSize::new(100, 100),
+            dimensions(0, 0),
+            &settings((0, 0, 0, 0)),
+        );
+        assert_eq!(zero_font, dimensions(100, 100));
+    }
+
+    #[test]
+    fn resizes_are_sent_once_the_size_settles() {
+        let mut controller = ResizeController::new();
+        controller.attached(dimensions(80, 24));
+        controller.request(dimensions(90, 30));
+        assert_eq!(controller.poll(), None);
+        sleep(RESIZE_DEBOUNCE / 2);
+        // A new size restarts the debounce period
+        controller.request(dimensions(100, 40));
+        sleep(RESIZE_DEBOUNCE / 2);
+        assert_eq!(controller.poll(), None);
+        sleep(RESIZE_DEBOUNCE);
+        assert_eq!(controller.poll(), Some(dimensions(100, 40)));
+        assert_eq!(controller.poll(), None);
+    }
+
+    #[test]
+    fn repeated_requests_do_not_delay_the_resize() {
+        let mut controller = ResizeController::new();
+        controller.request(dimensions(90, 30));
+        sleep(RESIZE_DEBOUNCE);
+        controller.request(dimensions(90, 30));
+        assert_eq!(controller.poll(), Some(dimensions(90, 30)));
+    }
+
+    #[test]
+    fn returning_to_the_current_size_cancels_the_resize() {
+        let mut controller = ResizeController::new();
+        controller.attached(dimensions(80, 24));
+        controller.request(dimensions(90, 30));
+        controller.request(dimensions(80, 24));
+        sleep(RESIZE_DEBOUNCE);
+        assert_eq!(controller.poll(), None);
+
+        controller.request(dimensions(90, 30));
+        sleep(RESIZE_DEBOUNCE);
+        assert_eq!(controller.poll(), Some(dimensions(90, 30)));
+        controller.request(dimensions(90, 30));
+        sleep(RESIZE_DEBOUNCE);
+        assert_eq!(controller.poll(), None);
+    }
+}